@@ -1,7 +1,7 @@
 import pandas as pd
 import plotly.express as px
 
-df = pd.read_csv('output.csv')
+df = pd.read_csv('output.csv', comment='#')
 
 fig = px.line(df, x = 'timestep', y = 'energy', title='energy per timestep')
 fig.show()
\ No newline at end of file
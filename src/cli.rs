@@ -0,0 +1,253 @@
+use clap::{App, AppSettings, Arg, SubCommand};
+
+// -----------------------------------
+// Every way of running the binary. main.rs dispatches on the subcommand name; with no subcommand at all
+// it opens the GUI on parameters.toml, same as it always did.
+// -----------------------------------
+fn is_unsigned(v: String) -> Result<(), String> {
+    v.parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| format!("expected a non-negative integer, got \"{}\"", v))
+}
+
+// Seeds end up in .params.toml and .meta.toml, and TOML integers stop at i64::MAX
+fn is_seed(v: String) -> Result<(), String> {
+    match v.parse::<u64>() {
+        Ok(seed) if seed <= i64::MAX as u64 => Ok(()),
+        _ => Err(format!("expected a seed between 0 and {}, got \"{}\"", i64::MAX, v)),
+    }
+}
+
+// Images are at most 65535 pixels a side, which is all a GIF can hold
+fn is_image_side(v: String) -> Result<(), String> {
+    match v.parse::<u64>() {
+        Ok(side) if side >= 1 && side <= u64::from(u16::MAX) => Ok(()),
+        _ => Err(format!("expected a number of pixels between 1 and {}, got \"{}\"", u16::MAX, v)),
+    }
+}
+
+fn is_assignment(v: String) -> Result<(), String> {
+    if v.contains('=') {
+        Ok(())
+    } else {
+        Err(format!("expected key=value, got \"{}\"", v))
+    }
+}
+
+fn params_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("params")
+        .short("p")
+        .long("params")
+        .value_name("FILE")
+        .default_value("parameters.toml")
+        .help("Parameters file")
+}
+
+fn steps_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("steps")
+        .short("n")
+        .long("steps")
+        .value_name("N")
+        .required(true)
+        .validator(is_unsigned)
+        .help("Timestep to stop at")
+}
+
+fn output_arg<'a, 'b>(help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name("output").short("o").long("output").value_name("PATH").help(help)
+}
+
+fn seed_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("seed")
+        .long("seed")
+        .value_name("SEED")
+        .validator(is_seed)
+        .help("Seed for the random number generator, instead of the parameters file's")
+}
+
+fn set_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("set")
+        .long("set")
+        .value_name("KEY=VALUE")
+        .multiple(true)
+        .number_of_values(1)
+        .validator(is_assignment)
+        .help("Overrides a parameter after every file is read, e.g. --set how_smooth=4 (can be repeated)")
+}
+
+pub fn app() -> App<'static, 'static> {
+    App::new("sars")
+        .about("Simulated annealing of a thick cortical surface")
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(
+            SubCommand::with_name("gui")
+                .about("Runs the simulation in a window")
+                .args(&[params_arg(), seed_arg(), set_arg()]),
+        )
+        .subcommand(SubCommand::with_name("my-gui").about("Opens the conrod GUI, which reads parameters.toml"))
+        .subcommand(SubCommand::with_name("draw").about("Draws a surface by hand, with parameters.toml for optimizing it"))
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Plays back the snapshots of a headless run (space, arrows, home/end, a timestep + enter)")
+                .arg(
+                    Arg::with_name("snapshots")
+                        .value_name("SNAPSHOTS")
+                        .required(true)
+                        .help("<output_file_path>.snapshots.csv"),
+                )
+                .arg(
+                    Arg::with_name("csv")
+                        .long("csv")
+                        .value_name("CSV")
+                        .help("Recorder values to show, <output_file_path> by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Runs the simulation headless, recording to the output CSV")
+                .args(&[
+                    params_arg(),
+                    steps_arg(),
+                    output_arg("Output CSV, instead of the parameters file's output_file_path"),
+                    seed_arg(),
+                    set_arg(),
+                ])
+                .arg(
+                    Arg::with_name("replicas")
+                        .long("replicas")
+                        .value_name("N")
+                        .validator(is_unsigned)
+                        .conflicts_with("tempering")
+                        .help("Runs N copies with seeds seed, seed + 1, ... in parallel and summarizes them"),
+                )
+                .arg(
+                    Arg::with_name("tempering")
+                        .long("tempering")
+                        .help("Runs parallel tempering with the parameters file's parallel_tempering settings"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sweep")
+                .about("Runs a grid of parameter overrides in parallel")
+                .arg(
+                    Arg::with_name("spec")
+                        .value_name("SWEEP_FILE")
+                        .required(true)
+                        .help("Sweep file, see sweep.toml"),
+                )
+                .args(&[
+                    steps_arg(),
+                    output_arg("Output directory, instead of the sweep file's output_directory"),
+                    set_arg(),
+                ])
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .value_name("N")
+                        .validator(is_unsigned)
+                        .help("How many runs at once, instead of the sweep file's threads"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("resume")
+                .about("Continues a headless run from its checkpoint")
+                .arg(
+                    Arg::with_name("checkpoint")
+                        .value_name("CHECKPOINT")
+                        .required(true)
+                        .help("<output_file_path>.checkpoint.json"),
+                )
+                .arg(steps_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Summarizes recorder CSVs: mean, std, min and max of every recorder at every timestep")
+                .arg(
+                    Arg::with_name("csvs")
+                        .value_name("CSV")
+                        .required(true)
+                        .multiple(true)
+                        .help("Output CSVs of runs with the same recorders"),
+                )
+                .arg(output_arg("Writes the summary here instead of printing the last timestep's")),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes one surface of a checkpoint or snapshots file as an SVG, a PNG or coordinates, or all of them as a GIF")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("<output_file_path>.checkpoint.json or <output_file_path>.snapshots.csv"),
+                )
+                .arg(
+                    Arg::with_name("timestep")
+                        .long("timestep")
+                        .value_name("T")
+                        .validator(is_unsigned)
+                        .help("Which snapshot to take, the last one by default"),
+                )
+                .arg(output_arg("Ends in .svg, .png or .gif for a picture, otherwise gets timestep,layer,x,y coordinates").required(true))
+                .arg(Arg::with_name("convex-hull").long("convex-hull").help("SVG: draws the convex hull of the outer surface"))
+                .arg(Arg::with_name("stitching").long("stitching").help("SVG: draws the stitching correspondences"))
+                .arg(Arg::with_name("gray-matter").long("gray-matter").help("SVG: fills in the gray matter"))
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .value_name("PIXELS")
+                        .validator(is_image_side)
+                        .help("PNG and GIF: width and height, 800 by default"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("conrod").setting(AppSettings::Hidden))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<clap::ArgMatches<'static>, clap::Error> {
+        app().get_matches_from_safe(args.iter().cloned())
+    }
+
+    #[test]
+    fn run_takes_named_options() {
+        let m = parse(&[
+            "sars",
+            "run",
+            "-p",
+            "p.toml",
+            "--steps",
+            "100",
+            "--seed",
+            "7",
+            "--set",
+            "how_smooth=4",
+            "--set",
+            "engine=in_place",
+        ])
+        .unwrap();
+        let run = m.subcommand_matches("run").unwrap();
+        assert_eq!(run.value_of("params"), Some("p.toml"));
+        assert_eq!(run.value_of("steps"), Some("100"));
+        assert_eq!(run.value_of("seed"), Some("7"));
+        assert_eq!(
+            run.values_of("set").unwrap().collect::<Vec<&str>>(),
+            vec!["how_smooth=4", "engine=in_place"]
+        );
+    }
+
+    #[test]
+    fn misuse_is_an_error() {
+        assert!(parse(&["sars", "run"]).is_err()); // <- no --steps
+        assert!(parse(&["sars", "run", "--steps", "many"]).is_err());
+        assert!(parse(&["sars", "run", "--steps", "10", "--set", "how_smooth"]).is_err());
+        assert!(parse(&["sars", "run", "--steps", "10", "--replicas", "3", "--tempering"]).is_err());
+        assert!(parse(&["sars", "smart"]).is_err());
+        assert!(parse(&["sars", "run", "--steps", "10", "--seed", "9223372036854775808"]).is_err()); // <- i64::MAX + 1
+        assert!(parse(&["sars", "run", "--steps", "10", "--seed", "9223372036854775807"]).is_ok());
+        assert!(parse(&["sars", "export", "in.csv", "-o", "out.png", "--size", "0"]).is_err());
+        assert!(parse(&["sars", "export", "in.csv", "-o", "out.gif", "--size", "70000"]).is_err());
+        assert!(parse(&["sars", "export", "in.csv", "-o", "out.gif", "--size", "65535"]).is_ok());
+    }
+}
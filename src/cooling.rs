@@ -0,0 +1,88 @@
+use simulated_annealing::SimState;
+
+// How much of the acceptance rate is remembered from one step to the next (exponential moving average)
+const ACCEPTANCE_MEMORY: f64 = 0.99;
+
+// -----------------------------------
+// Cooling schedules, chosen in the parameters file with e.g.
+// cooling_schedule = {kind = "exponential", rate = 0.9995}
+// Every schedule starts at initial_temperature.
+// -----------------------------------
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CoolingSchedule {
+    Linear { slope: f64 },                                // T0 + slope * t, never below 0
+    Exponential { rate: f64 },                            // T0 * rate^t
+    Logarithmic { c: f64 },                               // T0 / (1 + c * ln(1 + t))
+    Adaptive { target_acceptance: f64, adjustment: f64 }, // T * exp(adjustment * (target_acceptance - acceptance_rate))
+}
+
+pub fn temperature(schedule: &CoolingSchedule, initial_temperature: f64, sim_state: &SimState) -> f64 {
+    let t = sim_state.timestep as f64;
+    match schedule {
+        CoolingSchedule::Linear { slope } => {
+            let new = initial_temperature + t * slope;
+            if new < 0.0 {
+                0.0
+            } else {
+                new
+            }
+        }
+        CoolingSchedule::Exponential { rate } => initial_temperature * rate.powf(t),
+        CoolingSchedule::Logarithmic { c } => initial_temperature / (1.0 + c * (1.0 + t).ln()),
+        CoolingSchedule::Adaptive {
+            target_acceptance,
+            adjustment,
+        } => sim_state.temperature * (adjustment * (target_acceptance - sim_state.acceptance_rate)).exp(),
+    }
+}
+
+pub fn update_acceptance_rate(sim_state: &mut SimState, accepted: bool) {
+    let accepted = if accepted { 1.0 } else { 0.0 };
+    sim_state.acceptance_rate = ACCEPTANCE_MEMORY * sim_state.acceptance_rate + (1.0 - ACCEPTANCE_MEMORY) * accepted;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::default_params;
+
+    fn sim_state_at(timestep: u64) -> SimState {
+        let p = default_params();
+        let mut s = SimState::initial_state(&p);
+        s.timestep = timestep;
+        s
+    }
+
+    #[test]
+    fn schedules_start_at_initial_temperature() {
+        let s = sim_state_at(0);
+        let schedules = vec![
+            CoolingSchedule::Linear { slope: -0.05 },
+            CoolingSchedule::Exponential { rate: 0.99 },
+            CoolingSchedule::Logarithmic { c: 2.0 },
+        ];
+        for schedule in schedules {
+            assert_eq!(temperature(&schedule, 10.0, &s), 10.0);
+        }
+    }
+
+    #[test]
+    fn linear_schedule_bottoms_out_at_zero() {
+        assert_eq!(temperature(&CoolingSchedule::Linear { slope: -0.05 }, 10.0, &sim_state_at(100)), 5.0);
+        assert_eq!(temperature(&CoolingSchedule::Linear { slope: -0.05 }, 10.0, &sim_state_at(1000)), 0.0);
+    }
+
+    #[test]
+    fn adaptive_schedule_cools_when_accepting_too_much() {
+        let mut s = sim_state_at(10);
+        let adaptive = CoolingSchedule::Adaptive {
+            target_acceptance: 0.5,
+            adjustment: 0.1,
+        };
+        s.temperature = 1.0;
+        s.acceptance_rate = 0.9;
+        assert!(temperature(&adaptive, 10.0, &s) < 1.0);
+        s.acceptance_rate = 0.1;
+        assert!(temperature(&adaptive, 10.0, &s) > 1.0);
+    }
+}
@@ -0,0 +1,193 @@
+use graph;
+use graph::types::{NodeChangeMap, ThickSurface, INNER, OUTER};
+use types::Params;
+
+// -----------------------------------
+// The energy of a surface is a weighted sum of named terms, listed in the parameters file as
+// energy_terms = [{name = "white_matter", weight = 1.0}, ...]. Adding a term means implementing
+// EnergyTerm and registering it in term_from_name.
+// Terms don't look at the surface directly but at its Measures, which a step can update from just
+// the nodes it moves instead of walking both layers again (see Measures::after_changes).
+// -----------------------------------
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Measures {
+    pub outer_area: f64,
+    pub inner_area: f64,
+    pub outer_bending: f64,
+    pub inner_bending: f64,
+}
+
+impl Measures {
+    pub fn of(ts: &ThickSurface) -> Measures {
+        Measures {
+            outer_area: graph::area(&ts.layers[OUTER]),
+            inner_area: graph::area(&ts.layers[INNER]),
+            outer_bending: graph::bending_energy(&ts.layers[OUTER]),
+            inner_bending: graph::bending_energy(&ts.layers[INNER]),
+        }
+    }
+
+    // What the measures would be after applying the changes to that layer of ts. Only looks at the changed nodes and their neighbours.
+    pub fn after_changes(&self, ts: &ThickSurface, layer: usize, changes: &NodeChangeMap) -> Measures {
+        let area_delta = graph::area_delta(&ts.layers[layer], changes);
+        let bending_delta = graph::bending_energy_delta(&ts.layers[layer], changes);
+        match layer {
+            OUTER => Measures {
+                outer_area: self.outer_area + area_delta,
+                outer_bending: self.outer_bending + bending_delta,
+                ..*self
+            },
+            INNER => Measures {
+                inner_area: self.inner_area + area_delta,
+                inner_bending: self.inner_bending + bending_delta,
+                ..*self
+            },
+            _ => panic!("no such layer: {}", layer),
+        }
+    }
+}
+
+pub trait EnergyTerm {
+    fn name(&self) -> &'static str;
+    fn value(&self, m: &Measures, p: &Params) -> f64;
+}
+
+// am * white_matter^ap
+pub struct WhiteMatter;
+
+impl EnergyTerm for WhiteMatter {
+    fn name(&self) -> &'static str {
+        "white_matter"
+    }
+    fn value(&self, m: &Measures, p: &Params) -> f64 {
+        p.am * m.inner_area.powf(p.ap)
+    }
+}
+
+// dm * (1 + gray_matter_stretch)^dp, where the stretch is how far the gray matter area is from the initial (relaxed) one
+pub struct GrayMatterStretch;
+
+impl EnergyTerm for GrayMatterStretch {
+    fn name(&self) -> &'static str {
+        "gray_matter_stretch"
+    }
+    fn value(&self, m: &Measures, p: &Params) -> f64 {
+        let white_matter = m.inner_area;
+        let gray_matter = (m.outer_area - white_matter).abs();
+        let gray_matter_stretch = (gray_matter - p.initial_gray_matter_area).abs();
+        p.dm * (1.0 + gray_matter_stretch).powf(p.dp)
+    }
+}
+
+// Resistance of each layer to bending, see graph::bending_energy. One term per layer so they can be weighted separately.
+pub struct OuterBending;
+
+impl EnergyTerm for OuterBending {
+    fn name(&self) -> &'static str {
+        "outer_bending"
+    }
+    fn value(&self, m: &Measures, _p: &Params) -> f64 {
+        m.outer_bending
+    }
+}
+
+pub struct InnerBending;
+
+impl EnergyTerm for InnerBending {
+    fn name(&self) -> &'static str {
+        "inner_bending"
+    }
+    fn value(&self, m: &Measures, _p: &Params) -> f64 {
+        m.inner_bending
+    }
+}
+
+pub fn term_from_name(n: &str) -> Option<&'static dyn EnergyTerm> {
+    match n {
+        "white_matter" => Some(&WhiteMatter),
+        "gray_matter_stretch" => Some(&GrayMatterStretch),
+        "outer_bending" => Some(&OuterBending),
+        "inner_bending" => Some(&InnerBending),
+        _ => None,
+    }
+}
+
+fn term(n: &str) -> &'static dyn EnergyTerm {
+    match term_from_name(n) {
+        Some(t) => t,
+        None => panic!("unsupported energy term: {}", n),
+    }
+}
+
+// Weighted value of every configured term, in the order they appear in the parameters file
+pub fn energy_terms_of(m: &Measures, p: &Params) -> Vec<(String, f64)> {
+    p.energy_terms
+        .iter()
+        .map(|(name, weight)| {
+            let t = term(name);
+            let v = if *weight == 0.0 { 0.0 } else { weight * t.value(m, p) };
+            (String::from(t.name()), v)
+        })
+        .collect()
+}
+
+pub fn energy_of(m: &Measures, p: &Params) -> f64 {
+    energy_terms_of(m, p).iter().map(|(_, v)| v).sum()
+}
+
+pub fn energy_terms(ts: &ThickSurface, p: &Params) -> Vec<(String, f64)> {
+    energy_terms_of(&Measures::of(ts), p)
+}
+
+pub fn energy(ts: &ThickSurface, p: &Params) -> f64 {
+    energy_of(&Measures::of(ts), p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::default_params;
+    use graph::circular_thick_surface;
+    use optimizer;
+    use optimizer::Engine;
+    use simulated_annealing::SimState;
+
+    #[test]
+    fn default_terms_match_the_old_hardcoded_energy() {
+        let p = default_params();
+        let ts = circular_thick_surface(0.9, 0.1, 100);
+        let white_matter = graph::area(&ts.layers[INNER]);
+        let gray_matter_stretch = ((graph::area(&ts.layers[OUTER]) - white_matter).abs() - p.initial_gray_matter_area).abs();
+
+        assert!((energy(&ts, &p) - (white_matter + (1.0 + gray_matter_stretch).powf(2.0))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn terms_are_weighted() {
+        let mut p = default_params();
+        let ts = circular_thick_surface(0.9, 0.1, 100);
+        p.energy_terms = vec![(String::from("white_matter"), 3.0)];
+
+        assert!((energy(&ts, &p) - 3.0 * WhiteMatter.value(&Measures::of(&ts), &p)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn incremental_energy_matches_full_recomputation() {
+        let mut p = default_params();
+        p.initial_num_points = 80;
+        p.energy_terms = vec![
+            (String::from("white_matter"), 1.0),
+            (String::from("gray_matter_stretch"), 1.0),
+            (String::from("outer_bending"), 0.5),
+            (String::from("inner_bending"), 0.5),
+        ];
+        for engine in vec![Engine::InPlace, Engine::Cloning] {
+            p.engine = engine;
+            let mut s = SimState::initial_state(&p);
+            for _ in 0..300 {
+                optimizer::step(&mut s, &p);
+                assert!((energy_of(&s.measures, &p) - energy(&s.ts, &p)).abs() < 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use simulated_annealing::SimState;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use types::Params;
+
+// -----------------------------------
+// A checkpoint is everything needed to pick a run back up exactly where it stopped: both layers with their
+// node links, the stitching, timestep, temperature and RNG state, plus the parameters the run was started with.
+// -----------------------------------
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub params: Params,
+    pub sim_state: SimState,
+}
+
+pub fn checkpoint_file_path(p: &Params) -> String {
+    format!("{}.checkpoint.json", p.output_file_path)
+}
+
+pub fn should_checkpoint(sim_state: &SimState, p: &Params) -> bool {
+    match p.checkpoint_interval {
+        Some(interval) => interval > 0 && sim_state.timestep % interval == 0,
+        None => false,
+    }
+}
+
+pub fn save(sim_state: &SimState, p: &Params) -> std::io::Result<()> {
+    let path = checkpoint_file_path(p);
+    // Write somewhere else first so dying mid-write never leaves a broken checkpoint behind
+    let tmp_path = format!("{}.tmp", path);
+    {
+        let f = BufWriter::new(File::create(&tmp_path)?);
+        let checkpoint = Checkpoint {
+            params: p.clone(),
+            sim_state: sim_state.clone(),
+        };
+        serde_json::to_writer(f, &checkpoint)?;
+    }
+    std::fs::rename(tmp_path, path)
+}
+
+pub fn load(path: &str) -> std::io::Result<Checkpoint> {
+    let f = BufReader::new(File::open(path)?);
+    let mut checkpoint: Checkpoint = serde_json::from_reader(f)?;
+    checkpoint.sim_state.rebuild_caches();
+    Ok(checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use file_io::default_params;
+    use simulated_annealing_dumber_and_better::step;
+
+    #[test]
+    fn resumed_run_is_identical_to_uninterrupted_one() {
+        let mut p = default_params();
+        p.initial_num_points = 60;
+        let dir = ScratchDir::new("checkpoint");
+        p.output_file_path = dir.path("output.csv");
+
+        let mut uninterrupted = SimState::initial_state(&p);
+        for _ in 0..100 {
+            step(&mut uninterrupted, &p);
+        }
+
+        let mut interrupted = SimState::initial_state(&p);
+        for _ in 0..50 {
+            step(&mut interrupted, &p);
+        }
+        save(&interrupted, &p).unwrap();
+        let mut resumed = load(&checkpoint_file_path(&p)).unwrap().sim_state;
+        for _ in 0..50 {
+            step(&mut resumed, &p);
+        }
+
+        assert_eq!(resumed.timestep, uninterrupted.timestep);
+        assert_eq!(resumed.temperature, uninterrupted.temperature);
+        assert_eq!(resumed.ts.layers[0].nodes, uninterrupted.ts.layers[0].nodes);
+        assert_eq!(resumed.ts.layers[1].nodes, uninterrupted.ts.layers[1].nodes);
+    }
+}
@@ -0,0 +1,264 @@
+use graph::cyclic_graph_from_coords;
+use graph::types::ThickSurface;
+use linalg_helpers::point_in_ring;
+use std::collections::BTreeMap;
+
+// -----------------------------------
+// A segmented slice as a two-label mask image: the cortex in white, everything else (outside the slice and the white
+// matter inside it) in black, or the other way around with `invert`. The outline of the white region is traced with
+// marching squares: the contour around the most area is the outer surface and the largest hole inside it the white
+// matter boundary. Both are simplified down to `num_points` nodes, scaled and centred together to fit in [-1, 1]
+// with the y axis pointing up, like the renderer's, and go counterclockwise.
+// -----------------------------------
+type Point = (f64, f64);
+
+const LEVEL: f64 = 0.5; // <- between black and white; anti-aliased edges land in between pixels accordingly
+const FILL: f64 = 0.95; // <- of [-1, 1] the contours take up, like the default initial_radius
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+// Brightness in [0, 1] at every pixel, transparent counting as black
+struct Field {
+    width: i64,
+    height: i64,
+    values: Vec<f64>,
+}
+
+impl Field {
+    // Outside the image is below the level, so every contour closes
+    fn at(&self, x: i64, y: i64) -> f64 {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            0.0
+        } else {
+            self.values[(y * self.width + x) as usize]
+        }
+    }
+}
+
+// Where a contour crosses the edges between pixel centres: (0, x, y) is the one from (x, y) to (x + 1, y) and
+// (1, x, y) the one from (x, y) to (x, y + 1)
+type EdgeKey = (u8, i64, i64);
+
+// Every closed contour at LEVEL, with what's above the level on the same side of all of them, so outlines go one way
+// around and holes the other. Ordered by edge so the same image always comes out the same
+fn marching_squares(field: &Field) -> Vec<Vec<Point>> {
+    let mut next: BTreeMap<EdgeKey, (EdgeKey, Point)> = BTreeMap::new();
+    for y in -1..field.height {
+        for x in -1..field.width {
+            // Corners clockwise from the top left, and the edges after each corner
+            let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+            let edges: [EdgeKey; 4] = [(0, x, y), (1, x + 1, y), (0, x, y + 1), (1, x, y)];
+            let values: Vec<f64> = corners.iter().map(|(cx, cy)| field.at(*cx, *cy)).collect();
+            let inside: Vec<bool> = values.iter().map(|v| *v >= LEVEL).collect();
+            let crossing = |e: usize| {
+                let (a, b) = (e, (e + 1) % 4);
+                let t = (LEVEL - values[a]) / (values[b] - values[a]);
+                let ((ax, ay), (bx, by)) = (corners[a], corners[b]);
+                (ax as f64 + t * (bx - ax) as f64, ay as f64 + t * (by - ay) as f64)
+            };
+            let crossed: Vec<usize> = (0..4).filter(|e| inside[*e] != inside[(*e + 1) % 4]).collect();
+            // Pairs of crossed edges, each with a corner on one side of the segment between them
+            let pairs: Vec<(usize, usize, usize)> = match crossed.len() {
+                2 => vec![(crossed[0], crossed[1], (crossed[0] + 1) % 4)],
+                4 => {
+                    // A saddle: the middle of the cell decides whether the two corners that are above the level connect
+                    let middle = values.iter().sum::<f64>() / 4.0 >= LEVEL;
+                    let cut = if middle == inside[0] { [1, 3] } else { [0, 2] };
+                    // The corner after edge e is e + 1, so corner c sits between edges c - 1 and c
+                    cut.iter().map(|c| ((c + 3) % 4, *c, *c)).collect()
+                }
+                _ => vec![],
+            };
+            for (e1, e2, corner) in pairs {
+                let (p, q) = (crossing(e1), crossing(e2));
+                let c = (corners[corner].0 as f64, corners[corner].1 as f64);
+                let left = (q.0 - p.0) * (c.1 - p.1) - (q.1 - p.1) * (c.0 - p.0) > 0.0;
+                if left == inside[corner] {
+                    next.insert(edges[e1], (edges[e2], p));
+                } else {
+                    next.insert(edges[e2], (edges[e1], q));
+                }
+            }
+        }
+    }
+
+    let mut ret = Vec::new();
+    // Each ring from the smallest edge left
+    while let Some(mut key) = next.keys().next().cloned() {
+        let mut ring = Vec::new();
+        while let Some((to, p)) = next.remove(&key) {
+            ring.push(p);
+            key = to;
+        }
+        ret.push(from_lowest_leftmost(ring));
+    }
+    ret
+}
+
+// The same ring starting from its leftmost point, the lowest of those once y points up, so simplifying it doesn't
+// depend on where tracing happened to start
+fn from_lowest_leftmost(mut ring: Vec<Point>) -> Vec<Point> {
+    let start = (0..ring.len())
+        .min_by(|a, b| {
+            let ((ax, ay), (bx, by)) = (ring[*a], ring[*b]);
+            (ax, -ay).partial_cmp(&(bx, -by)).unwrap()
+        })
+        .unwrap_or(0);
+    ring.rotate_left(start);
+    ring
+}
+
+fn signed_area(ring: &[Point]) -> f64 {
+    (0..ring.len())
+        .map(|i| {
+            let ((x1, y1), (x2, y2)) = (ring[i], ring[(i + 1) % ring.len()]);
+            x1 * y2 - x2 * y1
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn triangle_area((x1, y1): Point, (x2, y2): Point, (x3, y3): Point) -> f64 {
+    ((x2 - x1) * (y3 - y1) - (x3 - x1) * (y2 - y1)).abs() / 2.0
+}
+
+// Visvalingam-Whyatt: drops the node whose triangle with its neighbours is smallest until `target` are left, which
+// gets rid of the pixel staircase first and keeps the corners
+fn simplify(ring: &[Point], target: usize) -> Vec<Point> {
+    let mut ret = ring.to_vec();
+    while ret.len() > target.max(3) {
+        let n = ret.len();
+        let smallest = (0..n)
+            .map(|i| (i, triangle_area(ret[(i + n - 1) % n], ret[i], ret[(i + 1) % n])))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap()
+            .0;
+        ret.remove(smallest);
+    }
+    ret
+}
+
+pub fn read_mask(file_path: &str, num_points: usize, invert: bool) -> std::io::Result<ThickSurface> {
+    let bad = |msg: String| invalid_data(format!("{}: {}", file_path, msg));
+    let img = image::open(file_path).map_err(|e| bad(e.to_string()))?.to_luma_alpha();
+    let field = Field {
+        width: img.width() as i64,
+        height: img.height() as i64,
+        values: img
+            .pixels()
+            .map(|p| {
+                let v = p[0] as f64 / 255.0 * p[1] as f64 / 255.0;
+                if invert {
+                    1.0 - v
+                } else {
+                    v
+                }
+            })
+            .collect(),
+    };
+
+    let contours = marching_squares(&field);
+    let outer = match contours
+        .iter()
+        .max_by(|a, b| signed_area(a).abs().partial_cmp(&signed_area(b).abs()).unwrap())
+    {
+        Some(o) => o,
+        None => return Err(bad(String::from("the mask is empty"))),
+    };
+    // Holes go the other way around from outlines
+    let outline_sign = signed_area(outer).signum();
+    let inner = contours
+        .iter()
+        .filter(|c| signed_area(c).signum() != outline_sign && point_in_ring(outer, c[0].0, c[0].1))
+        .max_by(|a, b| signed_area(a).abs().partial_cmp(&signed_area(b).abs()).unwrap());
+    let inner = match inner {
+        Some(i) => i,
+        None => return Err(bad(String::from("the cortex doesn't go around any white matter"))),
+    };
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for (x, y) in outer {
+        min_x = min_x.min(*x);
+        max_x = max_x.max(*x);
+        min_y = min_y.min(*y);
+        max_y = max_y.max(*y);
+    }
+    let scale = 2.0 * FILL / (max_x - min_x).max(max_y - min_y);
+    let (centre_x, centre_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let normalized = |ring: &[Point]| {
+        let mut ret: Vec<Point> = simplify(ring, num_points)
+            .iter()
+            .map(|(x, y)| ((x - centre_x) * scale, -(y - centre_y) * scale))
+            .collect();
+        if signed_area(&ret) < 0.0 {
+            ret.reverse();
+        }
+        ret
+    };
+    Ok(ThickSurface::new(
+        cyclic_graph_from_coords(&normalized(outer)),
+        cyclic_graph_from_coords(&normalized(inner)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use graph::area;
+    use graph::types::{INNER, OUTER};
+    use image::{GrayImage, Luma};
+    use std::f64::consts::PI;
+
+    // A ring of cortex from radius 20 to 40 around (50, 50), plus a speck outside it
+    fn ring_mask(dir: &ScratchDir, cortex: u8, rest: u8) -> String {
+        let path = dir.path(&format!("ring_{}.png", cortex));
+        let img = GrayImage::from_fn(100, 100, |x, y| {
+            let r = ((x as f64 - 50.0).powi(2) + (y as f64 - 50.0).powi(2)).sqrt();
+            Luma([if (20.0..=40.0).contains(&r) || (x == 95 && y == 95) {
+                cortex
+            } else {
+                rest
+            }])
+        });
+        img.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn contours_are_traced_simplified_and_go_counterclockwise() {
+        let dir = ScratchDir::new("mask_ring");
+        for (path, invert) in &[(ring_mask(&dir, 255, 0), false), (ring_mask(&dir, 0, 255), true)] {
+            let ts = read_mask(path, 60, *invert).unwrap();
+            assert_eq!(ts.layers[OUTER].nodes.len(), 60);
+            assert_eq!(ts.layers[INNER].nodes.len(), 60);
+            let (outer, inner) = (area(&ts.layers[OUTER]), area(&ts.layers[INNER]));
+            assert!(outer > 0.0 && inner > 0.0);
+            // The outer contour fills [-0.95, 0.95], and the hole is half as wide
+            assert!((outer - PI * 0.95 * 0.95).abs() < 0.1, "{}", outer);
+            assert!((inner / outer - 0.25).abs() < 0.03, "{}", inner / outer);
+        }
+    }
+
+    #[test]
+    fn the_same_mask_gives_the_same_surface() {
+        let dir = ScratchDir::new("mask_again");
+        let path = ring_mask(&dir, 255, 0);
+        let (a, b) = (read_mask(&path, 60, false).unwrap(), read_mask(&path, 60, false).unwrap());
+        for layer in &[OUTER, INNER] {
+            assert_eq!(a.layers[*layer].to_vec_of_points(), b.layers[*layer].to_vec_of_points());
+        }
+    }
+
+    #[test]
+    fn a_mask_without_a_hole_is_rejected() {
+        let dir = ScratchDir::new("mask_disk");
+        let path = dir.path("disk.png");
+        GrayImage::from_fn(20, 20, |x, y| Luma([if x > 5 && x < 15 && y > 5 && y < 15 { 255 } else { 0 }]))
+            .save(&path)
+            .unwrap();
+        assert!(read_mask(&path, 20, false).is_err());
+    }
+}
@@ -1,42 +1,838 @@
+pub mod checkpoint;
+pub mod mask_import;
+pub mod periodic_csv;
+pub mod provenance;
 pub mod recorders;
+#[cfg(test)]
+pub mod scratch;
+pub mod summary;
+pub mod surface;
+pub mod svg_import;
+pub mod thickness;
 
-use std::f64::consts::PI;
+use rand::Rng;
+use std::fmt;
+use std::path::Path;
+use cooling::CoolingSchedule;
+use energy;
+use graph;
+use initial_shape;
+use initial_shape::InitialShape;
+use optimizer::Engine;
+use parallel_tempering::TemperingParams;
+use renderer::raster::{FrameParams, DEFAULT_GIF_DELAY, DEFAULT_SIZE};
+use renderer::svg::{SvgOptions, SvgParams};
 use types;
 
-pub fn toml_table_to_params(table: toml::Value) -> types::Params {
-    match table {
-        toml::Value::Table(m) => {
-            let initial_radius = m.get("initial_radius").unwrap().as_float().unwrap();
-            let initial_thickness = m.get("initial_thickness").unwrap().as_float().unwrap();
-            let initial_area = PI * (initial_radius.powf(2.0) - (initial_radius - initial_thickness).powf(2.0));
-            types::Params {
-                initial_thickness: initial_thickness,
-                initial_radius: initial_radius,
-                initial_gray_matter_area: initial_area,
-                initial_num_points: m.get("initial_num_points").unwrap().as_integer().unwrap() as usize,
-                initial_temperature: m.get("initial_temperature").unwrap().as_float().unwrap(),
-                compression_factor: m.get("compression_factor").unwrap().as_float().unwrap(),
-                softness_factor: m.get("softness_factor").unwrap().as_float().unwrap(),
-                how_smooth: m.get("how_smooth").unwrap().as_integer().unwrap() as usize,
-                max_merge_steps_away: m.get("max_merge_steps_away").unwrap().as_integer().unwrap() as usize,
-                node_addition_threshold: m.get("node_addition_threshold").unwrap().as_float().unwrap(),
-                node_deletion_threshold: m.get("node_deletion_threshold").unwrap().as_float().unwrap(),
-                low_high: (
-                    m.get("low_high").unwrap().as_array().unwrap()[0].as_float().unwrap(),
-                    m.get("low_high").unwrap().as_array().unwrap()[1].as_float().unwrap(),
-                ),
-                recorders: m
-                    .get("recorders")
-                    .unwrap()
-                    .as_array()
-                    .unwrap()
-                    .iter()
-                    .map(|x| String::from(x.as_str().unwrap()))
-                    .collect(),
-                temperature_param: m.get("temperature_param").unwrap().as_float().unwrap(),
-                output_file_path: String::from(m.get("output_file_path").unwrap().as_str().unwrap()),
-            }
-        }
-        _ => panic!("No key-value table found in parameters.toml"),
+const KNOWN_KEYS: &[&str] = &[
+    "initial_thickness",
+    "initial_radius",
+    "initial_num_points",
+    "initial_shape",
+    "initial_temperature",
+    "compression_factor",
+    "softness_factor",
+    "am",
+    "ap",
+    "dm",
+    "dp",
+    "energy_terms",
+    "how_smooth",
+    "max_merge_steps_away",
+    "node_addition_threshold",
+    "node_deletion_threshold",
+    "low_high",
+    "recorders",
+    "cooling_schedule",
+    "output_file_path",
+    "seed",
+    "checkpoint_interval",
+    "snapshot_interval",
+    "thickness_interval",
+    "svg",
+    "frames",
+    "engine",
+    "parallel_tempering",
+];
+
+// -----------------------------------
+// Everything that can be wrong with a parameters file. Keys are named the way they'd be written in the
+// file, so nested ones look like "cooling_schedule.slope" or "energy_terms[1].weight".
+// -----------------------------------
+#[derive(Debug)]
+pub enum ParamsError {
+    Unreadable { file: String, reason: String },
+    Missing { file: String, key: String },
+    WrongType { file: String, key: String, expected: &'static str, found: toml::Value },
+    Unsupported { file: String, key: String, value: String },
+    Inconsistent { file: String, reason: String },
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::Unreadable { file, reason } => write!(f, "{}: couldn't read parameters: {}", file, reason),
+            ParamsError::Missing { file, key } => write!(f, "{}: missing key \"{}\"", file, key),
+            ParamsError::WrongType { file, key, expected, found } => {
+                write!(f, "{}: \"{}\" should be {}, found {} {}", file, key, expected, found.type_str(), found)?;
+                match (*expected, found) {
+                    ("a float", toml::Value::Integer(i)) => write!(f, " (write it as {}.0)", i),
+                    ("a non-negative integer", toml::Value::Float(_)) => {
+                        write!(f, " (write it without a decimal point)")
+                    }
+                    _ => Ok(()),
+                }
+            }
+            ParamsError::Unsupported { file, key, value } => write!(f, "{}: unsupported {} \"{}\"", file, key, value),
+            ParamsError::Inconsistent { file, reason } => write!(f, "{}: {}", file, reason),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+// A table in the parameters file, knowing where it is so errors can say so
+struct Section<'a> {
+    m: &'a toml::value::Table,
+    file: &'a str,
+    path: String, // <- "" for the top level, "cooling_schedule." and such for nested tables
+}
+
+impl<'a> Section<'a> {
+    fn key(&self, k: &str) -> String {
+        format!("{}{}", self.path, k)
+    }
+
+    fn wrong_type(&self, k: &str, expected: &'static str, found: &toml::Value) -> ParamsError {
+        ParamsError::WrongType {
+            file: String::from(self.file),
+            key: self.key(k),
+            expected,
+            found: found.clone(),
+        }
+    }
+
+    fn get(&self, k: &str) -> Result<&'a toml::Value, ParamsError> {
+        match self.m.get(k) {
+            Some(v) => Ok(v),
+            None => Err(ParamsError::Missing {
+                file: String::from(self.file),
+                key: self.key(k),
+            }),
+        }
+    }
+
+    fn float(&self, k: &str) -> Result<f64, ParamsError> {
+        let v = self.get(k)?;
+        v.as_float().ok_or_else(|| self.wrong_type(k, "a float", v))
+    }
+
+    fn unsigned(&self, k: &str) -> Result<u64, ParamsError> {
+        let v = self.get(k)?;
+        match v.as_integer() {
+            Some(i) if i >= 0 => Ok(i as u64),
+            _ => Err(self.wrong_type(k, "a non-negative integer", v)),
+        }
+    }
+
+    // For values that end up in a u16, which would otherwise quietly wrap around
+    fn unsigned_u16(&self, k: &str) -> Result<u16, ParamsError> {
+        let v = self.get(k)?;
+        match v.as_integer() {
+            Some(i) if i >= 0 && i <= i64::from(u16::MAX) => Ok(i as u16),
+            _ => Err(self.wrong_type(k, "an integer between 0 and 65535", v)),
+        }
+    }
+
+    fn boolean(&self, k: &str) -> Result<bool, ParamsError> {
+        let v = self.get(k)?;
+        v.as_bool().ok_or_else(|| self.wrong_type(k, "true or false", v))
+    }
+
+    fn float_pair(&self, k: &str) -> Result<(f64, f64), ParamsError> {
+        let v = self.get(k)?;
+        match v.as_array().map(|a| a.iter().map(|x| x.as_float()).collect::<Vec<Option<f64>>>()) {
+            Some(ref a) if a.len() == 2 && a[0].is_some() && a[1].is_some() => Ok((a[0].unwrap(), a[1].unwrap())),
+            _ => Err(self.wrong_type(k, "a list of two floats", v)),
+        }
+    }
+
+    fn string(&self, k: &str) -> Result<&'a str, ParamsError> {
+        let v = self.get(k)?;
+        v.as_str().ok_or_else(|| self.wrong_type(k, "a string", v))
+    }
+
+    fn array(&self, k: &str) -> Result<&'a Vec<toml::Value>, ParamsError> {
+        let v = self.get(k)?;
+        v.as_array().ok_or_else(|| self.wrong_type(k, "a list", v))
+    }
+
+    fn section(&self, k: &str) -> Result<Section<'a>, ParamsError> {
+        let v = self.get(k)?;
+        Section::of(v, self.file, format!("{}.", self.key(k))).ok_or_else(|| self.wrong_type(k, "a table", v))
+    }
+
+    fn optional<T, F: Fn(&Self, &str) -> Result<T, ParamsError>>(&self, k: &str, f: F) -> Result<Option<T>, ParamsError> {
+        match self.m.get(k) {
+            Some(_) => f(self, k).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn of(v: &'a toml::Value, file: &'a str, path: String) -> Option<Section<'a>> {
+        v.as_table().map(|m| Section { m, file, path })
+    }
+}
+
+fn unsupported(file: &str, key: String, value: &str) -> ParamsError {
+    ParamsError::Unsupported {
+        file: String::from(file),
+        key,
+        value: String::from(value),
+    }
+}
+
+fn cooling_schedule_from_toml(s: &Section) -> Result<CoolingSchedule, ParamsError> {
+    Ok(match s.string("kind")? {
+        "linear" => CoolingSchedule::Linear { slope: s.float("slope")? },
+        "exponential" => CoolingSchedule::Exponential { rate: s.float("rate")? },
+        "logarithmic" => CoolingSchedule::Logarithmic { c: s.float("c")? },
+        "adaptive" => CoolingSchedule::Adaptive {
+            target_acceptance: s.float("target_acceptance")?,
+            adjustment: s.float("adjustment")?,
+        },
+        k => return Err(unsupported(s.file, s.key("kind"), k)),
+    })
+}
+
+fn energy_terms_from_toml(s: &Section) -> Result<Vec<(String, f64)>, ParamsError> {
+    let mut ret = Vec::new();
+    for (i, t) in s.array("energy_terms")?.iter().enumerate() {
+        let key = format!("energy_terms[{}]", i);
+        let term = match Section::of(t, s.file, format!("{}.", key)) {
+            Some(term) => term,
+            None => return Err(s.wrong_type(&key, "a table", t)),
+        };
+        let name = term.string("name")?;
+        if energy::term_from_name(name).is_none() {
+            return Err(unsupported(s.file, term.key("name"), name));
+        }
+        ret.push((String::from(name), term.float("weight")?));
+    }
+    Ok(ret)
+}
+
+fn recorders_from_toml(s: &Section) -> Result<Vec<String>, ParamsError> {
+    let mut ret = Vec::new();
+    for (i, r) in s.array("recorders")?.iter().enumerate() {
+        let key = format!("recorders[{}]", i);
+        let name = r.as_str().ok_or_else(|| s.wrong_type(&key, "a string", r))?;
+        if recorders::name_to_fn(name).is_none() {
+            return Err(unsupported(s.file, key, name));
+        }
+        ret.push(String::from(name));
+    }
+    Ok(ret)
+}
+
+fn svg_from_toml(s: &Section) -> Result<SvgParams, ParamsError> {
+    let mut timesteps = Vec::new();
+    for (i, t) in s.array("timesteps")?.iter().enumerate() {
+        match t.as_integer() {
+            Some(t) if t >= 0 => timesteps.push(t as u64),
+            _ => return Err(s.wrong_type(&format!("timesteps[{}]", i), "a non-negative integer", t)),
+        }
+    }
+    let flag = |k: &str| s.optional(k, Section::boolean).map(|b| b.unwrap_or(false));
+    Ok(SvgParams {
+        timesteps,
+        options: SvgOptions {
+            convex_hull: flag("convex_hull")?,
+            stitching: flag("stitching")?,
+            gray_matter: flag("gray_matter")?,
+        },
+    })
+}
+
+fn frames_from_toml(s: &Section) -> Result<FrameParams, ParamsError> {
+    let size = |k: &str| s.optional(k, Section::unsigned_u16).map(|v| v.map(u32::from).unwrap_or(DEFAULT_SIZE));
+    Ok(FrameParams {
+        interval: s.unsigned("interval")?,
+        width: size("width")?,
+        height: size("height")?,
+        gif: s.optional("gif", Section::boolean)?.unwrap_or(false),
+        gif_delay: s.optional("gif_delay", Section::unsigned_u16)?.unwrap_or(DEFAULT_GIF_DELAY),
+    })
+}
+
+fn initial_shape_from_toml(s: &Section) -> Result<InitialShape, ParamsError> {
+    Ok(match s.string("kind")? {
+        "circle" => InitialShape::Circle,
+        "ellipse" => InitialShape::Ellipse {
+            semi_axes: s.float_pair("semi_axes")?,
+        },
+        "superellipse" => InitialShape::Superellipse {
+            semi_axes: s.float_pair("semi_axes")?,
+            exponent: s.float("exponent")?,
+        },
+        "perturbed_circle" => InitialShape::PerturbedCircle {
+            amplitude: s.float("amplitude")?,
+            harmonics: s.optional("harmonics", Section::unsigned)?.unwrap_or(8) as usize,
+        },
+        "file" => InitialShape::File {
+            path: String::from(s.string("path")?),
+            timestep: s.optional("timestep", Section::unsigned)?,
+        },
+        "mask" => InitialShape::Mask {
+            path: String::from(s.string("path")?),
+            invert: s.optional("invert", Section::boolean)?.unwrap_or(false),
+        },
+        "svg" => InitialShape::Svg {
+            path: String::from(s.string("path")?),
+            // Both ids or neither
+            ids: match s.optional("outer_id", Section::string)? {
+                Some(outer) => Some((String::from(outer), String::from(s.string("inner_id")?))),
+                None => match s.optional("inner_id", Section::string)? {
+                    Some(_) => return Err(ParamsError::Missing {
+                        file: String::from(s.file),
+                        key: s.key("outer_id"),
+                    }),
+                    None => None,
+                },
+            },
+        },
+        k => return Err(unsupported(s.file, s.key("kind"), k)),
+    })
+}
+
+fn check_consistency(p: &types::Params, file: &str) -> Result<(), ParamsError> {
+    let inconsistent = |reason: String| {
+        Err(ParamsError::Inconsistent {
+            file: String::from(file),
+            reason,
+        })
+    };
+    if p.node_deletion_threshold >= p.node_addition_threshold {
+        return inconsistent(format!(
+            "node_deletion_threshold ({}) should be smaller than node_addition_threshold ({}), otherwise added nodes get merged right back",
+            p.node_deletion_threshold, p.node_addition_threshold
+        ));
+    }
+    if p.initial_thickness >= p.initial_radius {
+        return inconsistent(format!(
+            "initial_thickness ({}) should be smaller than initial_radius ({}), otherwise there's no inner surface",
+            p.initial_thickness, p.initial_radius
+        ));
+    }
+    if p.low_high.0 >= p.low_high.1 {
+        return inconsistent(format!("low_high = [{}, {}] should go from lower to higher", p.low_high.0, p.low_high.1));
+    }
+    if p.how_smooth > p.initial_num_points {
+        return inconsistent(format!(
+            "how_smooth ({}) is larger than the number of nodes (initial_num_points = {})",
+            p.how_smooth, p.initial_num_points
+        ));
+    }
+    if let Some(f) = &p.frames {
+        // Parsing already keeps them below 65536, what a GIF can hold
+        if f.width == 0 || f.height == 0 {
+            return inconsistent(format!("frames are {}x{} pixels, both sides should be between 1 and {}", f.width, f.height, u16::MAX));
+        }
+    }
+    // The temperature ladder is geometric, so it can't start at or below 0
+    if let Some(t) = &p.parallel_tempering {
+        // With fewer there's no pair to swap, so it would just be one slower run
+        if t.replicas < 2 {
+            return inconsistent(format!("parallel_tempering.replicas ({}) should be at least 2", t.replicas));
+        }
+        if !(0.0 < p.initial_temperature && p.initial_temperature < t.max_temperature) {
+            return inconsistent(format!(
+                "parallel tempering needs 0 < initial_temperature < parallel_tempering.max_temperature, got {} and {}",
+                p.initial_temperature, t.max_temperature
+            ));
+        }
+    }
+    Ok(())
+}
+
+// `file` is only used to say where problems are
+pub fn params_from_toml(table: toml::Value, file: &str) -> Result<types::Params, ParamsError> {
+    let s = match Section::of(&table, file, String::new()) {
+        Some(s) => s,
+        None => {
+            return Err(ParamsError::Unreadable {
+                file: String::from(file),
+                reason: String::from("no key-value table found"),
+            })
+        }
+    };
+    for k in s.m.keys() {
+        if !KNOWN_KEYS.contains(&k.as_str()) {
+            println!("Warning: {}: unknown key \"{}\" is ignored", file, k);
+        }
+    }
+
+    let mut p = types::Params {
+        initial_thickness: s.float("initial_thickness")?,
+        initial_radius: s.float("initial_radius")?,
+        initial_shape: match s.optional("initial_shape", Section::section)? {
+            Some(shape) => initial_shape_from_toml(&shape)?,
+            None => InitialShape::Circle,
+        },
+        initial_gray_matter_area: 0.0, // <- worked out from the shape itself below
+        am: s.float("am")?,
+        ap: s.float("ap")?,
+        dm: s.float("dm")?,
+        dp: s.float("dp")?,
+        energy_terms: energy_terms_from_toml(&s)?,
+        initial_num_points: s.unsigned("initial_num_points")? as usize,
+        initial_temperature: s.float("initial_temperature")?,
+        compression_factor: s.float("compression_factor")?,
+        softness_factor: s.float("softness_factor")?,
+        how_smooth: s.unsigned("how_smooth")? as usize,
+        max_merge_steps_away: s.unsigned("max_merge_steps_away")? as usize,
+        node_addition_threshold: s.float("node_addition_threshold")?,
+        node_deletion_threshold: s.float("node_deletion_threshold")?,
+        low_high: s.float_pair("low_high")?,
+        recorders: recorders_from_toml(&s)?,
+        cooling_schedule: cooling_schedule_from_toml(&s.section("cooling_schedule")?)?,
+        output_file_path: String::from(s.string("output_file_path")?),
+        // Runs without a seed get a random one, which still ends up recorded so they can be reproduced
+        seed: match s.optional("seed", Section::unsigned)? {
+            Some(seed) => seed,
+            None => rand::thread_rng().gen_range(0, i64::MAX as u64), // <- small enough to write back as a TOML integer
+        },
+        checkpoint_interval: s.optional("checkpoint_interval", Section::unsigned)?,
+        snapshot_interval: s.optional("snapshot_interval", Section::unsigned)?,
+        thickness_interval: s.optional("thickness_interval", Section::unsigned)?,
+        svg: match s.optional("svg", Section::section)? {
+            Some(svg) => Some(svg_from_toml(&svg)?),
+            None => None,
+        },
+        frames: match s.optional("frames", Section::section)? {
+            Some(frames) => Some(frames_from_toml(&frames)?),
+            None => None,
+        },
+        engine: match s.optional("engine", Section::string)? {
+            Some(e) => match Engine::from_name(e) {
+                Some(engine) => engine,
+                None => return Err(unsupported(file, String::from("engine"), e)),
+            },
+            None => Engine::Cloning,
+        },
+        parallel_tempering: match s.optional("parallel_tempering", Section::section)? {
+            Some(t) => Some(TemperingParams {
+                replicas: t.unsigned("replicas")? as usize,
+                max_temperature: t.float("max_temperature")?,
+                swap_interval: t.unsigned("swap_interval")?,
+            }),
+            None => None,
+        },
+    };
+    check_consistency(&p, file)?;
+    p.initial_gray_matter_area = match initial_shape::thick_surface(&p) {
+        Ok(ts) => graph::gray_matter_area(&ts),
+        Err(reason) => {
+            return Err(ParamsError::Inconsistent {
+                file: String::from(file),
+                reason: format!("initial_shape: {}", reason),
+            })
+        }
+    };
+    Ok(p)
+}
+
+// -----------------------------------
+// Parameters come in layers, each overriding the keys of the one before:
+//     defaults.toml (embedded) < the file it `extends` (and so on) < the file < --set key=value
+// Paths in `extends` and initial_shape's `path` are relative to the file that has them.
+// -----------------------------------
+const DEFAULTS: &str = include_str!("defaults.toml");
+
+pub fn defaults_table() -> toml::value::Table {
+    match DEFAULTS.parse::<toml::Value>() {
+        Ok(toml::Value::Table(t)) => t,
+        _ => panic!("the embedded defaults.toml isn't a table"),
+    }
+}
+
+// What the tests start from, so editing parameters.toml for an experiment doesn't change them
+#[cfg(test)]
+pub fn default_params() -> types::Params {
+    match params_from_toml(toml::Value::Table(defaults_table()), "defaults.toml") {
+        Ok(p) => p,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn read_table(path: &str) -> Result<toml::value::Table, ParamsError> {
+    let unreadable = |reason: String| ParamsError::Unreadable {
+        file: String::from(path),
+        reason,
+    };
+    let content = std::fs::read_to_string(path).map_err(|e| unreadable(e.to_string()))?;
+    match content.parse::<toml::Value>().map_err(|e| unreadable(e.to_string()))? {
+        toml::Value::Table(t) => Ok(t),
+        _ => Err(unreadable(String::from("no key-value table found"))),
+    }
+}
+
+pub fn relative_to(file: &str, path: &str) -> String {
+    Path::new(file).parent().unwrap_or_else(|| Path::new("")).join(path).to_string_lossy().into_owned()
+}
+
+// The file's keys on top of everything it extends. `chain` is the files on the way here, to catch loops
+fn file_layers(path: &str, chain: &mut Vec<String>) -> Result<toml::value::Table, ParamsError> {
+    if chain.iter().any(|p| p == path) {
+        chain.push(String::from(path));
+        return Err(ParamsError::Inconsistent {
+            file: chain[0].clone(),
+            reason: format!("\"extends\" goes around in a loop: {}", chain.join(" -> ")),
+        });
+    }
+    chain.push(String::from(path));
+    let mut table = read_table(path)?;
+    // Made absolute where it can be, so the parameters a run writes out find it again from wherever they end up
+    if let Some(toml::Value::String(shape_path)) = table.get_mut("initial_shape").and_then(|s| s.get_mut("path")) {
+        let resolved = relative_to(path, shape_path);
+        *shape_path = match std::fs::canonicalize(&resolved) {
+            Ok(absolute) => absolute.to_string_lossy().into_owned(),
+            Err(_) => resolved,
+        };
+    }
+    match table.remove("extends") {
+        None => Ok(table),
+        Some(toml::Value::String(base)) => {
+            let mut merged = file_layers(&relative_to(path, &base), chain)?;
+            for (k, v) in table {
+                merged.insert(k, v);
+            }
+            Ok(merged)
+        }
+        Some(other) => Err(ParamsError::WrongType {
+            file: String::from(path),
+            key: String::from("extends"),
+            expected: "a string",
+            found: other,
+        }),
+    }
+}
+
+// "how_smooth=4" or "cooling_schedule.slope=-0.1". The value is read as TOML, so strings can go without quotes
+pub fn apply_override(table: &mut toml::value::Table, assignment: &str) -> Result<(), ParamsError> {
+    let bad = |reason: &str| ParamsError::Inconsistent {
+        file: String::from("--set"),
+        reason: format!("\"{}\" {}", assignment, reason),
+    };
+    let eq = assignment.find('=').ok_or_else(|| bad("should look like key=value"))?;
+    let (key, raw) = (assignment[..eq].trim(), assignment[eq + 1..].trim());
+    let value = match format!("v = {}", raw).parse::<toml::Value>() {
+        Ok(mut t) => t.as_table_mut().unwrap().remove("v").unwrap(),
+        Err(_) => toml::Value::String(String::from(raw)),
+    };
+    let mut path: Vec<&str> = key.split('.').collect();
+    let last = path.pop().unwrap();
+    let mut t = table;
+    for k in path {
+        t = match t.get_mut(k).and_then(|v| v.as_table_mut()) {
+            Some(inner) => inner,
+            None => return Err(bad(&format!("sets a key inside {}, which isn't a table", k))),
+        };
+    }
+    t.insert(String::from(last), value);
+    Ok(())
+}
+
+pub fn layered_table(path: &str, overrides: &[String]) -> Result<toml::value::Table, ParamsError> {
+    let mut table = defaults_table();
+    for (k, v) in file_layers(path, &mut Vec::new())? {
+        table.insert(k, v);
+    }
+    for o in overrides {
+        apply_override(&mut table, o)?;
+    }
+    Ok(table)
+}
+
+pub fn params_from_file_with_overrides(path: &str, overrides: &[String]) -> Result<types::Params, ParamsError> {
+    params_from_toml(toml::Value::Table(layered_table(path, overrides)?), path)
+}
+
+pub fn params_from_file(path: &str) -> Result<types::Params, ParamsError> {
+    params_from_file_with_overrides(path, &[])
+}
+
+// The other way around, so a run can leave behind exactly what it ran with
+pub fn params_to_toml(p: &types::Params) -> toml::Value {
+    let mut t = toml::value::Table::new();
+    let mut put = |k: &str, v: toml::Value| {
+        t.insert(String::from(k), v);
+    };
+    let table = |kvs: Vec<(&str, toml::Value)>| toml::Value::Table(kvs.into_iter().map(|(k, v)| (String::from(k), v)).collect());
+    let schedule = |kind: &str, kvs: Vec<(&str, f64)>| {
+        let mut kvs: Vec<(&str, toml::Value)> = kvs.into_iter().map(|(k, v)| (k, toml::Value::Float(v))).collect();
+        kvs.push(("kind", toml::Value::String(String::from(kind))));
+        table(kvs)
+    };
+    let pair = |(a, b): (f64, f64)| toml::Value::Array(vec![toml::Value::Float(a), toml::Value::Float(b)]);
+    put("initial_thickness", toml::Value::Float(p.initial_thickness));
+    put("initial_radius", toml::Value::Float(p.initial_radius));
+    put("initial_num_points", toml::Value::Integer(p.initial_num_points as i64));
+    let mut shape = match &p.initial_shape {
+        InitialShape::Circle => vec![],
+        InitialShape::Ellipse { semi_axes } => vec![("semi_axes", pair(*semi_axes))],
+        InitialShape::Superellipse { semi_axes, exponent } => vec![("semi_axes", pair(*semi_axes)), ("exponent", toml::Value::Float(*exponent))],
+        InitialShape::PerturbedCircle { amplitude, harmonics } => {
+            vec![("amplitude", toml::Value::Float(*amplitude)), ("harmonics", toml::Value::Integer(*harmonics as i64))]
+        }
+        InitialShape::File { path, timestep } => {
+            let mut kvs = vec![("path", toml::Value::String(path.clone()))];
+            if let Some(t) = timestep {
+                kvs.push(("timestep", toml::Value::Integer(*t as i64)));
+            }
+            kvs
+        }
+        InitialShape::Mask { path, invert } => vec![("path", toml::Value::String(path.clone())), ("invert", toml::Value::Boolean(*invert))],
+        InitialShape::Svg { path, ids } => {
+            let mut kvs = vec![("path", toml::Value::String(path.clone()))];
+            if let Some((outer, inner)) = ids {
+                kvs.push(("outer_id", toml::Value::String(outer.clone())));
+                kvs.push(("inner_id", toml::Value::String(inner.clone())));
+            }
+            kvs
+        }
+    };
+    shape.push(("kind", toml::Value::String(String::from(p.initial_shape.name()))));
+    put("initial_shape", table(shape));
+    put("initial_temperature", toml::Value::Float(p.initial_temperature));
+    put("compression_factor", toml::Value::Float(p.compression_factor));
+    put("softness_factor", toml::Value::Float(p.softness_factor));
+    put("am", toml::Value::Float(p.am));
+    put("ap", toml::Value::Float(p.ap));
+    put("dm", toml::Value::Float(p.dm));
+    put("dp", toml::Value::Float(p.dp));
+    put(
+        "energy_terms",
+        toml::Value::Array(
+            p.energy_terms
+                .iter()
+                .map(|(name, weight)| table(vec![("name", toml::Value::String(name.clone())), ("weight", toml::Value::Float(*weight))]))
+                .collect(),
+        ),
+    );
+    put("how_smooth", toml::Value::Integer(p.how_smooth as i64));
+    put("max_merge_steps_away", toml::Value::Integer(p.max_merge_steps_away as i64));
+    put("node_addition_threshold", toml::Value::Float(p.node_addition_threshold));
+    put("node_deletion_threshold", toml::Value::Float(p.node_deletion_threshold));
+    put("low_high", pair(p.low_high));
+    put("recorders", toml::Value::Array(p.recorders.iter().map(|r| toml::Value::String(r.clone())).collect()));
+    put(
+        "cooling_schedule",
+        match p.cooling_schedule {
+            CoolingSchedule::Linear { slope } => schedule("linear", vec![("slope", slope)]),
+            CoolingSchedule::Exponential { rate } => schedule("exponential", vec![("rate", rate)]),
+            CoolingSchedule::Logarithmic { c } => schedule("logarithmic", vec![("c", c)]),
+            CoolingSchedule::Adaptive { target_acceptance, adjustment } => {
+                schedule("adaptive", vec![("target_acceptance", target_acceptance), ("adjustment", adjustment)])
+            }
+        },
+    );
+    put("output_file_path", toml::Value::String(p.output_file_path.clone()));
+    put("seed", toml::Value::Integer(p.seed as i64));
+    if let Some(interval) = p.checkpoint_interval {
+        put("checkpoint_interval", toml::Value::Integer(interval as i64));
+    }
+    if let Some(interval) = p.snapshot_interval {
+        put("snapshot_interval", toml::Value::Integer(interval as i64));
+    }
+    if let Some(interval) = p.thickness_interval {
+        put("thickness_interval", toml::Value::Integer(interval as i64));
+    }
+    if let Some(svg) = &p.svg {
+        put(
+            "svg",
+            table(vec![
+                ("timesteps", toml::Value::Array(svg.timesteps.iter().map(|t| toml::Value::Integer(*t as i64)).collect())),
+                ("convex_hull", toml::Value::Boolean(svg.options.convex_hull)),
+                ("stitching", toml::Value::Boolean(svg.options.stitching)),
+                ("gray_matter", toml::Value::Boolean(svg.options.gray_matter)),
+            ]),
+        );
+    }
+    if let Some(f) = &p.frames {
+        put(
+            "frames",
+            table(vec![
+                ("interval", toml::Value::Integer(f.interval as i64)),
+                ("width", toml::Value::Integer(f.width as i64)),
+                ("height", toml::Value::Integer(f.height as i64)),
+                ("gif", toml::Value::Boolean(f.gif)),
+                ("gif_delay", toml::Value::Integer(f.gif_delay as i64)),
+            ]),
+        );
+    }
+    put("engine", toml::Value::String(String::from(p.engine.name())));
+    if let Some(tp) = &p.parallel_tempering {
+        put(
+            "parallel_tempering",
+            table(vec![
+                ("replicas", toml::Value::Integer(tp.replicas as i64)),
+                ("max_temperature", toml::Value::Float(tp.max_temperature)),
+                ("swap_interval", toml::Value::Integer(tp.swap_interval as i64)),
+            ]),
+        );
+    }
+    toml::Value::Table(t)
+}
+
+// output.csv -> output.csv.params.toml
+pub fn effective_params_file_path(output_file_path: &str) -> String {
+    format!("{}.params.toml", output_file_path)
+}
+
+pub fn write_effective_params(p: &types::Params) -> std::io::Result<()> {
+    let path = effective_params_file_path(&p.output_file_path);
+    let content = toml::to_string(&params_to_toml(p)).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    std::fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use linalg_helpers::circular_points;
+
+    fn error_with(k: &str, v: Option<toml::Value>) -> String {
+        let mut t = defaults_table();
+        match v {
+            Some(v) => t.insert(String::from(k), v),
+            None => t.remove(k),
+        };
+        match params_from_toml(toml::Value::Table(t), "test.toml") {
+            Ok(_) => panic!("a bad {} should have been rejected", k),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn default_parameters_are_fine() {
+        assert!(params_from_toml(toml::Value::Table(defaults_table()), "parameters.toml").is_ok());
+    }
+
+    #[test]
+    fn errors_name_the_key_and_file() {
+        assert_eq!(error_with("initial_temperature", None), "test.toml: missing key \"initial_temperature\"");
+        assert_eq!(
+            error_with("initial_temperature", Some(toml::Value::Integer(10))),
+            "test.toml: \"initial_temperature\" should be a float, found integer 10 (write it as 10.0)"
+        );
+        assert_eq!(
+            error_with("how_smooth", Some(toml::Value::Float(8.0))),
+            "test.toml: \"how_smooth\" should be a non-negative integer, found float 8.0 (write it without a decimal point)"
+        );
+        let schedule = "x = {kind = \"linear\", slope = 1}".parse::<toml::Value>().unwrap()["x"].clone();
+        assert!(error_with("cooling_schedule", Some(schedule)).contains("\"cooling_schedule.slope\" should be a float"));
+        let frames = |f: &str| format!("x = {{interval = 10, gif = true, {}}}", f).parse::<toml::Value>().unwrap()["x"].clone();
+        assert!(error_with("frames", Some(frames("gif_delay = 70000"))).contains("\"frames.gif_delay\" should be an integer between 0 and 65535"));
+        assert!(error_with("frames", Some(frames("width = 70000, height = 10"))).contains("\"frames.width\" should be an integer between 0 and 65535"));
+        assert!(error_with("frames", Some(frames("width = 10, height = 0"))).contains("both sides should be between 1 and 65535"));
+    }
+
+    #[test]
+    fn inconsistent_values_are_rejected() {
+        assert!(error_with("node_deletion_threshold", Some(toml::Value::Float(0.02))).contains("node_deletion_threshold"));
+        assert!(error_with("initial_thickness", Some(toml::Value::Float(1.0))).contains("initial_thickness"));
+        assert!(error_with("low_high", Some(toml::Value::Array(vec![toml::Value::Float(0.2), toml::Value::Float(-0.2)]))).contains("low_high"));
+        assert!(error_with("how_smooth", Some(toml::Value::Integer(1000))).contains("how_smooth"));
+        let tempering = |replicas: i64, max: f64| {
+            format!("x = {{replicas = {}, max_temperature = {:?}, swap_interval = 10}}", replicas, max).parse::<toml::Value>().unwrap()["x"].clone()
+        };
+        assert!(error_with("parallel_tempering", Some(tempering(4, 5.0))).contains("initial_temperature < parallel_tempering.max_temperature"));
+        let mut t = defaults_table();
+        t.insert(String::from("initial_temperature"), toml::Value::Float(1.0));
+        for replicas in &[0, 1] {
+            t.insert(String::from("parallel_tempering"), tempering(*replicas, 5.0));
+            match params_from_toml(toml::Value::Table(t.clone()), "test.toml") {
+                Err(ParamsError::Inconsistent { reason, .. }) => assert!(reason.contains("parallel_tempering.replicas"), "{}", reason),
+                _ => panic!("{} replicas should have been rejected", replicas),
+            }
+        }
+        t.insert(String::from("parallel_tempering"), tempering(2, 5.0));
+        assert!(params_from_toml(toml::Value::Table(t.clone()), "test.toml").is_ok());
+        t.insert(String::from("initial_temperature"), toml::Value::Float(-1.0));
+        assert!(params_from_toml(toml::Value::Table(t.clone()), "test.toml").is_err());
+        t.insert(String::from("initial_temperature"), toml::Value::Float(1.0));
+        assert!(params_from_toml(toml::Value::Table(t), "test.toml").is_ok());
+    }
+
+    #[test]
+    fn defaults_are_fine_on_their_own() {
+        assert!(params_from_toml(toml::Value::Table(defaults_table()), "defaults.toml").is_ok());
+    }
+
+    #[test]
+    fn layers_override_in_order() {
+        let dir = ScratchDir::new("layers");
+        dir.write("base.toml", "how_smooth = 4\ncompression_factor = 1.3\n");
+        let child = dir.write(
+            "child.toml",
+            "extends = \"base.toml\"\nhow_smooth = 6\ncooling_schedule = {kind = \"linear\", slope = -0.05}\n",
+        );
+        let overrides = vec![String::from("cooling_schedule.slope=-0.1"), String::from("engine=in_place")];
+        let p = params_from_file_with_overrides(&child, &overrides).unwrap();
+        assert_eq!(p.how_smooth, 6); // <- the file beats what it extends
+        assert_eq!(p.compression_factor, 1.3); // <- what it extends beats the defaults
+        assert_eq!(p.initial_num_points, 250); // <- the defaults fill in the rest
+        assert_eq!(p.cooling_schedule, CoolingSchedule::Linear { slope: -0.1 }); // <- --set beats everything
+        assert_eq!(p.engine, Engine::InPlace);
+    }
+
+    #[test]
+    fn extends_loops_are_caught() {
+        let dir = ScratchDir::new("loop");
+        dir.write("a.toml", "extends = \"b.toml\"\n");
+        let b = dir.write("b.toml", "extends = \"a.toml\"\n");
+        match params_from_file(&b) {
+            Err(ParamsError::Inconsistent { reason, .. }) => assert!(reason.contains("loop"), "{}", reason),
+            _ => panic!("a loop of extends should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn shape_files_are_found_next_to_the_params_file() {
+        let dir = ScratchDir::new("shape_path");
+        let mut csv = String::from("timestep,layer,x,y\n");
+        for (layer, radius) in [1.0, 0.8].iter().enumerate() {
+            for (x, y) in circular_points(0.0, 0.0, *radius, 20) {
+                csv.push_str(&format!("0,{},{},{}\n", layer, x, y));
+            }
+        }
+        dir.write("shape.csv", &csv);
+        dir.write("base.toml", "initial_shape = {kind = \"file\", path = \"shape.csv\"}\n");
+        let child = dir.write("child.toml", "extends = \"base.toml\"\n");
+        // Not wherever the tests happen to run from
+        let p = params_from_file(&child).unwrap();
+        assert_eq!(
+            p.initial_shape,
+            InitialShape::File {
+                path: std::fs::canonicalize(dir.path("shape.csv")).unwrap().to_string_lossy().into_owned(),
+                timestep: None
+            }
+        );
+    }
+
+    #[test]
+    fn written_params_read_back_the_same() {
+        let mut t = defaults_table();
+        t.insert(String::from("seed"), toml::Value::Integer(42));
+        t.insert(String::from("checkpoint_interval"), toml::Value::Integer(100));
+        t.insert(String::from("snapshot_interval"), toml::Value::Integer(50));
+        t.insert(String::from("thickness_interval"), toml::Value::Integer(25));
+        t.insert(String::from("svg"), "x = {timesteps = [0, 100], gray_matter = true}".parse::<toml::Value>().unwrap()["x"].clone());
+        t.insert(String::from("frames"), "x = {interval = 10, width = 300, gif = true}".parse::<toml::Value>().unwrap()["x"].clone());
+        let p = params_from_toml(toml::Value::Table(t), "parameters.toml").unwrap();
+        let written = toml::to_string(&params_to_toml(&p)).unwrap();
+        let read_back = params_from_toml(written.parse::<toml::Value>().unwrap(), "written").unwrap();
+        assert_eq!(params_to_toml(&read_back), params_to_toml(&p));
     }
 }
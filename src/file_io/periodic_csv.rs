@@ -0,0 +1,66 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+
+// -----------------------------------
+// A CSV a run appends rows to every `interval` timesteps (if there is an interval) and, through `finish`, once more at
+// the end, starting with the timestep. What the rows are is up to the closure `record` and `finish` get, which is only
+// called when rows are due.
+// -----------------------------------
+pub struct PeriodicCsv {
+    f: BufWriter<File>,
+    interval: Option<u64>,
+    last_written: Option<u64>,
+}
+
+impl PeriodicCsv {
+    pub fn create(path: &str, header: &str, interval: Option<u64>) -> std::io::Result<PeriodicCsv> {
+        let mut f = BufWriter::new(File::create(path)?);
+        writeln!(f, "{}", header)?;
+        Ok(PeriodicCsv {
+            f,
+            interval,
+            last_written: None,
+        })
+    }
+
+    // For a run resumed at `timestep`: keeps the rows from before it and appends from there
+    pub fn resume(path: &str, header: &str, interval: Option<u64>, timestep: u64) -> std::io::Result<PeriodicCsv> {
+        let kept: Vec<String> = match std::fs::read_to_string(path) {
+            Ok(content) => content
+                .lines()
+                .filter(|l| match l.split(',').next().unwrap().parse::<u64>() {
+                    Ok(t) => t < timestep,
+                    Err(_) => true, // <- the header
+                })
+                .map(|l| format!("{}\n", l))
+                .collect(),
+            Err(_) => return PeriodicCsv::create(path, header, interval),
+        };
+        std::fs::write(path, kept.concat())?;
+        Ok(PeriodicCsv {
+            f: BufWriter::new(OpenOptions::new().append(true).open(path)?),
+            interval,
+            last_written: None,
+        })
+    }
+
+    fn write<F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>>(&mut self, timestep: u64, write_rows: F) -> std::io::Result<()> {
+        write_rows(&mut self.f)?;
+        self.last_written = Some(timestep);
+        self.f.flush()
+    }
+
+    pub fn record<F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>>(&mut self, timestep: u64, write_rows: F) -> std::io::Result<()> {
+        match self.interval {
+            Some(interval) if interval > 0 && timestep % interval == 0 => self.write(timestep, write_rows),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn finish<F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>>(&mut self, timestep: u64, write_rows: F) -> std::io::Result<()> {
+        if self.last_written == Some(timestep) {
+            return Ok(());
+        }
+        self.write(timestep, write_rows)
+    }
+}
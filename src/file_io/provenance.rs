@@ -0,0 +1,198 @@
+use file_io::params_to_toml;
+use std::time::{SystemTime, UNIX_EPOCH};
+use stitcher;
+use types::Params;
+
+// -----------------------------------
+// What produced an output CSV, written next to it as <output_file_path>.meta.toml when recording starts and again,
+// with end_time and steps filled in, when recording stops. A run that got killed is the one without an end_time.
+// Resuming a run keeps what the file says about how it started and adds the timestep and time of the resume to
+// resumed_at, since the CSV still has the rows from before it.
+// -----------------------------------
+pub struct Provenance {
+    path: String,
+    params: Params,
+    start_time: toml::Value,
+    first_timestep: u64,
+    last_timestep: Option<u64>,
+    steps: u64,
+    resumed_at: Vec<toml::Value>, // <- {timestep, time} tables, oldest first
+}
+
+pub fn provenance_file_path(output_file_path: &str) -> String {
+    format!("{}.meta.toml", output_file_path)
+}
+
+// 1970-01-01T00:00:00Z and on, in UTC
+fn rfc3339(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // Days since the epoch to year/month/day, from Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+fn datetime(t: SystemTime) -> toml::Value {
+    toml::Value::Datetime(rfc3339(t).parse().unwrap())
+}
+
+impl Provenance {
+    // `first_timestep` is where recording starts: 0, or the timestep of the checkpoint a resumed run starts from
+    pub fn start(p: &Params, first_timestep: u64) -> Provenance {
+        let ret = Provenance {
+            path: provenance_file_path(&p.output_file_path),
+            params: p.clone(),
+            start_time: datetime(SystemTime::now()),
+            first_timestep,
+            last_timestep: None,
+            steps: 0,
+            resumed_at: Vec::new(),
+        };
+        ret.write_or_warn(None);
+        ret
+    }
+
+    // For a run resumed at `timestep`: carries on from the metadata the earlier segments left, or starts afresh at
+    // `timestep` if there's none to read
+    pub fn resume(p: &Params, timestep: u64) -> Provenance {
+        let path = provenance_file_path(&p.output_file_path);
+        let earlier = match std::fs::read_to_string(&path).map(|content| content.parse::<toml::Value>()) {
+            Ok(Ok(t)) => t,
+            _ => return Provenance::start(p, timestep),
+        };
+        let (start_time, first_timestep) = match (earlier.get("start_time"), earlier.get("first_timestep").and_then(|t| t.as_integer())) {
+            (Some(start_time), Some(first_timestep)) if first_timestep >= 0 => (start_time.clone(), first_timestep as u64),
+            _ => return Provenance::start(p, timestep),
+        };
+        let mut resumed_at = match earlier.get("resumed_at").and_then(|r| r.as_array()) {
+            Some(r) => r.clone(),
+            None => Vec::new(),
+        };
+        let mut resume = toml::value::Table::new();
+        resume.insert(String::from("timestep"), toml::Value::Integer(timestep as i64));
+        resume.insert(String::from("time"), datetime(SystemTime::now()));
+        resumed_at.push(toml::Value::Table(resume));
+        let ret = Provenance {
+            path,
+            params: p.clone(),
+            start_time,
+            first_timestep,
+            last_timestep: if timestep > first_timestep { Some(timestep - 1) } else { None },
+            // Every step gets recorded, and the CSV keeps the rows from before the checkpoint. A killed run never got
+            // to write its steps, so they're counted from there rather than read back.
+            steps: timestep.saturating_sub(first_timestep),
+            resumed_at,
+        };
+        ret.write_or_warn(None);
+        ret
+    }
+
+    pub fn recorded(&mut self, timestep: u64) {
+        self.last_timestep = Some(timestep);
+        self.steps += 1;
+    }
+
+    pub fn finish(&self) {
+        self.write_or_warn(Some(SystemTime::now()))
+    }
+
+    fn to_toml(&self, end_time: Option<SystemTime>) -> toml::Value {
+        let mut t = toml::value::Table::new();
+        t.insert(String::from("crate_version"), toml::Value::String(String::from(env!("CARGO_PKG_VERSION"))));
+        t.insert(String::from("seed"), toml::Value::Integer(self.params.seed as i64));
+        t.insert(String::from("engine"), toml::Value::String(String::from(self.params.engine.name())));
+        t.insert(String::from("stitching"), toml::Value::String(String::from(stitcher::DEFAULT_STRATEGY.name())));
+        t.insert(String::from("start_time"), self.start_time.clone());
+        if let Some(end) = end_time {
+            t.insert(String::from("end_time"), datetime(end));
+        }
+        t.insert(String::from("first_timestep"), toml::Value::Integer(self.first_timestep as i64));
+        if let Some(last) = self.last_timestep {
+            t.insert(String::from("last_timestep"), toml::Value::Integer(last as i64));
+        }
+        t.insert(String::from("steps"), toml::Value::Integer(self.steps as i64));
+        if !self.resumed_at.is_empty() {
+            t.insert(String::from("resumed_at"), toml::Value::Array(self.resumed_at.clone()));
+        }
+        t.insert(String::from("params"), params_to_toml(&self.params));
+        toml::Value::Table(t)
+    }
+
+    fn write_or_warn(&self, end_time: Option<SystemTime>) {
+        let written = toml::to_string(&self.to_toml(end_time))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            .and_then(|content| std::fs::write(&self.path, content));
+        if let Err(e) = written {
+            println!("Couldn't write run metadata to {}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use file_io::default_params;
+    use std::time::Duration;
+
+    #[test]
+    fn dates() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(951782400 + 3661)), "2000-02-29T01:01:01Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1790000000)), "2026-09-21T14:13:20Z");
+    }
+
+    #[test]
+    fn metadata_has_the_params_and_the_steps() {
+        let mut p = default_params();
+        let dir = ScratchDir::new("provenance");
+        p.output_file_path = dir.path("output.csv");
+        let mut provenance = Provenance::start(&p, 0);
+        for t in 0..10 {
+            provenance.recorded(t);
+        }
+        provenance.finish();
+
+        let written = std::fs::read_to_string(provenance_file_path(&p.output_file_path)).unwrap().parse::<toml::Value>().unwrap();
+        assert_eq!(written["steps"].as_integer(), Some(10));
+        assert_eq!(written["last_timestep"].as_integer(), Some(9));
+        assert_eq!(written["seed"].as_integer(), Some(p.seed as i64));
+        assert_eq!(written["params"]["how_smooth"].as_integer(), Some(p.how_smooth as i64));
+        assert!(written.get("end_time").is_some());
+    }
+
+    #[test]
+    fn resuming_keeps_how_the_run_started() {
+        let mut p = default_params();
+        let dir = ScratchDir::new("provenance_resume");
+        p.output_file_path = dir.path("output.csv");
+        let mut first = Provenance::start(&p, 0);
+        for t in 0..30 {
+            first.recorded(t);
+        }
+        drop(first); // <- killed, no end_time
+
+        let read = || std::fs::read_to_string(provenance_file_path(&p.output_file_path)).unwrap().parse::<toml::Value>().unwrap();
+        let start_time = read()["start_time"].clone();
+        let mut resumed = Provenance::resume(&p, 20);
+        for t in 20..40 {
+            resumed.recorded(t);
+        }
+        Provenance::resume(&p, 40).finish();
+
+        let written = read();
+        assert_eq!(written["start_time"], start_time);
+        assert_eq!(written["first_timestep"].as_integer(), Some(0));
+        assert_eq!(written["steps"].as_integer(), Some(40));
+        let resumed_at: Vec<i64> = written["resumed_at"].as_array().unwrap().iter().map(|r| r["timestep"].as_integer().unwrap()).collect();
+        assert_eq!(resumed_at, vec![20, 40]);
+    }
+}
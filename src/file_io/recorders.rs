@@ -1,32 +1,53 @@
 use graph;
-use simulated_annealing;
 
 use std::fs::File;
 use std::io::Write;
 
 use graph::types::{ThickSurface, INNER, OUTER};
+use parallel_tempering::ReplicaExchange;
 use simulated_annealing::SimState;
 use std::collections::HashMap;
 use types::Params;
+use file_io::provenance::Provenance;
+use file_io::surface::Snapshots;
+use file_io::thickness::ThicknessDump;
+use renderer::raster::Frames;
+use renderer::svg::{svg_file_path, write_svg};
+use stitcher::stitch_default;
 use graph::convex_hull::convex_hull_from_graph;
+use graph::folding;
+use graph::thickness;
 
 type RecorderFn = for<'r, 's> fn(&'r ThickSurface, &'s Params) -> f64;
 
 pub struct RecordingState {
     pub f: File,
     pub last_recorded: Vec<f64>,
+    pub provenance: Option<Provenance>, // <- None for the GUI's scratch file
+    pub snapshots: Option<Snapshots>,   // <- None for the GUI's scratch file and parallel tempering
+    pub frames: Option<Frames>,         // <- None unless the parameters have a frames section, same cases as snapshots
+    pub thickness: Option<ThicknessDump>, // <- None unless the parameters have a thickness_interval, same cases as snapshots
 }
 
+
 impl RecordingState {
     pub fn empty_state(file_path: &str) -> Option<RecordingState> {
         return match File::create(file_path) {
-            Ok(mut f) => Some(RecordingState { f, last_recorded: vec![] }),
+            Ok(mut f) => Some(RecordingState {
+                f,
+                last_recorded: vec![],
+                provenance: None,
+                snapshots: None,
+                frames: None,
+                thickness: None,
+            }),
             Err(e) => panic!("Couldn't write to file: {:?}", e),
         };
     }
     pub fn initial_state(p: &Params) -> Option<RecordingState> {
         if !p.recorders.is_empty() {
             let mut header = String::new();
+            header.push_str(&format!("# seed: {}\n", p.seed));
             header.push_str("timestep");
             for r in &p.recorders {
                 header.push_str(",");
@@ -39,6 +60,15 @@ impl RecordingState {
                     Ok(_) => Some(RecordingState {
                         f,
                         last_recorded: Vec::new(),
+                        provenance: Some(Provenance::start(p, 0)),
+                        snapshots: Some(
+                            Snapshots::create(&p.output_file_path, p.snapshot_interval)
+                                .unwrap_or_else(|e| panic!("Couldn't write to file: {:?}", e)),
+                        ),
+                        frames: p.frames.map(|f| Frames::create(&p.output_file_path, f)),
+                        thickness: p.thickness_interval.map(|i| {
+                            ThicknessDump::create(&p.output_file_path, i).unwrap_or_else(|e| panic!("Couldn't write to file: {:?}", e))
+                        }),
                     }),
                     Err(e) => panic!("Couldn't write to file: {:?}", e),
                 },
@@ -47,6 +77,74 @@ impl RecordingState {
         }
         None
     }
+    // One energy column per replica and one swap acceptance rate column per pair of neighbouring replicas
+    pub fn replica_exchange_state(p: &Params, re: &ReplicaExchange) -> Option<RecordingState> {
+        let mut header = String::new();
+        header.push_str(&format!("# seed: {}\n", p.seed));
+        let temperatures: Vec<String> = re.temperatures.iter().map(|t| t.to_string()).collect();
+        header.push_str(&format!("# temperatures: {}\n", temperatures.join(" ")));
+        header.push_str("timestep");
+        for i in 0..re.replicas.len() {
+            header.push_str(&format!(",replica {} energy", i));
+        }
+        for i in 0..re.swap_attempts.len() {
+            header.push_str(&format!(",swap {}-{} acceptance", i, i + 1));
+        }
+        header.push_str("\n");
+
+        return match File::create(&p.output_file_path) {
+            Ok(mut f) => match f.write_all(header.as_bytes()) {
+                Ok(_) => Some(RecordingState {
+                    f,
+                    last_recorded: Vec::new(),
+                    provenance: Some(Provenance::start(p, 0)),
+                    snapshots: None,
+                    frames: None,
+                    thickness: None,
+                }),
+                Err(e) => panic!("Couldn't write to file: {:?}", e),
+            },
+            Err(_) => None,
+        };
+    }
+    // Picks up the CSV of a run that is being resumed at `timestep`. Rows the dead run wrote past its last checkpoint
+    // are dropped, since the resumed run is about to record those timesteps again.
+    pub fn resume_state(p: &Params, timestep: u64) -> Option<RecordingState> {
+        if p.recorders.is_empty() {
+            return None;
+        }
+        let kept: Vec<String> = match std::fs::read_to_string(&p.output_file_path) {
+            Ok(content) => content
+                .lines()
+                .filter(|l| match l.split(',').next().unwrap().parse::<u64>() {
+                    Ok(t) => t < timestep,
+                    Err(_) => true, // seed and header lines
+                })
+                .map(|l| format!("{}\n", l))
+                .collect(),
+            Err(_) => return RecordingState::initial_state(p),
+        };
+        return match File::create(&p.output_file_path) {
+            Ok(mut f) => match f.write_all(kept.concat().as_bytes()) {
+                Ok(_) => Some(RecordingState {
+                    f,
+                    last_recorded: Vec::new(),
+                    provenance: Some(Provenance::resume(p, timestep)),
+                    snapshots: Some(
+                        Snapshots::resume(&p.output_file_path, p.snapshot_interval, timestep)
+                            .unwrap_or_else(|e| panic!("Couldn't write to file: {:?}", e)),
+                    ),
+                    frames: p.frames.map(|f| Frames::resume(&p.output_file_path, f, timestep)),
+                    thickness: p.thickness_interval.map(|i| {
+                        ThicknessDump::resume(&p.output_file_path, i, timestep)
+                            .unwrap_or_else(|e| panic!("Couldn't write to file: {:?}", e))
+                    }),
+                }),
+                Err(e) => panic!("Couldn't write to file: {:?}", e),
+            },
+            Err(_) => None,
+        };
+    }
 }
 
 fn outer_perimeter(ts: &ThickSurface, _p: &Params) -> f64 {
@@ -66,7 +164,7 @@ fn inner_area(ts: &ThickSurface, _p: &Params) -> f64 {
 }
 
 fn energy(ts: &ThickSurface, p: &Params) -> f64 {
-    simulated_annealing::energy(ts, p.initial_gray_matter_area)
+    ::energy::energy(ts, p)
 }
 
 fn gray_matter_area(ts: &ThickSurface, _p: &Params) -> f64 {
@@ -84,8 +182,40 @@ fn convex_perimeter (ts: &ThickSurface, _p: &Params) -> f64 { graph::perimeter(
 fn convex_gray_area (ts: &ThickSurface, _p: &Params) -> f64 {
     graph::area(&convex_hull_from_graph(&ts.layers[OUTER])) - graph::area(&ts.layers[INNER])
 }
+fn gyrification_index(ts: &ThickSurface, _p: &Params) -> f64 {
+    folding::gyrification_index(ts)
+}
+fn perimeter_ratio(ts: &ThickSurface, _p: &Params) -> f64 {
+    folding::perimeter_ratio(ts)
+}
+fn num_sulci(ts: &ThickSurface, _p: &Params) -> f64 {
+    folding::num_sulci(ts) as f64
+}
+fn num_gyri(ts: &ThickSurface, _p: &Params) -> f64 {
+    folding::num_gyri(ts) as f64
+}
+fn mean_fold_depth(ts: &ThickSurface, _p: &Params) -> f64 {
+    folding::mean_fold_depth(ts)
+}
+fn max_fold_depth(ts: &ThickSurface, _p: &Params) -> f64 {
+    folding::max_fold_depth(ts)
+}
+// Recorders don't get the stitching, so these go by the nearest inner segment. In the order of thickness_stats
+const THICKNESS_RECORDERS: [&str; 4] = ["mean thickness", "thickness std", "min thickness", "max thickness"];
+fn mean_thickness(ts: &ThickSurface, _p: &Params) -> f64 {
+    thickness::thickness_stats(&thickness::local_thickness(ts, None)).0
+}
+fn thickness_std(ts: &ThickSurface, _p: &Params) -> f64 {
+    thickness::thickness_stats(&thickness::local_thickness(ts, None)).1
+}
+fn min_thickness(ts: &ThickSurface, _p: &Params) -> f64 {
+    thickness::thickness_stats(&thickness::local_thickness(ts, None)).2
+}
+fn max_thickness(ts: &ThickSurface, _p: &Params) -> f64 {
+    thickness::thickness_stats(&thickness::local_thickness(ts, None)).3
+}
 
-fn name_to_fn(n: &str) -> Option<RecorderFn> {
+pub fn name_to_fn(n: &str) -> Option<RecorderFn> {
     match n {
         "energy" => Some(energy),
         "outer perimeter" => Some(outer_perimeter),
@@ -98,6 +228,16 @@ fn name_to_fn(n: &str) -> Option<RecorderFn> {
         "convex area" => Some(convex_area),
         "convex perimeter" => Some(convex_perimeter),
         "convex gray area" => Some(convex_gray_area),
+        "gyrification index" => Some(gyrification_index),
+        "perimeter ratio" => Some(perimeter_ratio),
+        "num sulci" => Some(num_sulci),
+        "num gyri" => Some(num_gyri),
+        "mean fold depth" => Some(mean_fold_depth),
+        "max fold depth" => Some(max_fold_depth),
+        "mean thickness" => Some(mean_thickness),
+        "thickness std" => Some(thickness_std),
+        "min thickness" => Some(min_thickness),
+        "max thickness" => Some(max_thickness),
         _ => None,
     }
 }
@@ -136,16 +276,82 @@ pub fn rec_map() -> HashMap<String, RecorderFn> {
         String::from("num outer points"),
         num_outer_points as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
     );
+    r.insert(
+        String::from("gyrification index"),
+        gyrification_index as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("perimeter ratio"),
+        perimeter_ratio as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("num sulci"),
+        num_sulci as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("num gyri"),
+        num_gyri as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("mean fold depth"),
+        mean_fold_depth as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("max fold depth"),
+        max_fold_depth as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("mean thickness"),
+        mean_thickness as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("thickness std"),
+        thickness_std as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("min thickness"),
+        min_thickness as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
+    r.insert(
+        String::from("max thickness"),
+        max_thickness as for<'r, 's> fn(&'r graph::types::ThickSurface, &'s Params) -> f64,
+    );
     r
 }
 
+pub fn record_replica_exchange(re: &ReplicaExchange, p: &Params, recording_state: &mut RecordingState) {
+    let mut new_vals: Vec<f64> = re.replicas.iter().map(|r| ::energy::energy(&r.ts, p)).collect();
+    new_vals.extend(re.swap_acceptance_rates());
+    let mut line = format!("{}", re.timestep);
+    for v in &new_vals {
+        line.push_str(format!(",{}", v).as_str());
+    }
+    line.push_str("\n");
+    match recording_state.f.write_all(line.as_bytes()) {
+        Ok(_) => {}
+        Err(e) => panic!("Couldn't write to file: {:?}", e),
+    }
+    recording_state.last_recorded = new_vals;
+    if let Some(provenance) = &mut recording_state.provenance {
+        provenance.recorded(re.timestep);
+    }
+}
+
 pub fn record(sim_state: &SimState, p: &Params, recording_state: &mut RecordingState) {
     let mut line = String::new();
     let mut new_vals = Vec::new();
+    // Every thickness recorder needs the thickness at every outer node, so they share one go at it
+    let thicknesses = if p.recorders.iter().any(|r| THICKNESS_RECORDERS.contains(&r.as_str())) {
+        let (mean, std, min, max) = thickness::thickness_stats(&thickness::local_thickness(&sim_state.ts, None));
+        vec![mean, std, min, max]
+    } else {
+        Vec::new()
+    };
     for r in &p.recorders {
-        let val = match name_to_fn(r) {
-            Some(recorder) => recorder(&sim_state.ts, p),
-            None => panic!(format!("unsupported recorder: {}", r)),
+        let val = match (THICKNESS_RECORDERS.iter().position(|t| t == r), name_to_fn(r)) {
+            (Some(i), _) => thicknesses[i],
+            (None, Some(recorder)) => recorder(&sim_state.ts, p),
+            (None, None) => panic!(format!("unsupported recorder: {}", r)),
         };
         new_vals.push(val);
         line.push_str(format!(",{}", val).as_str());
@@ -163,5 +369,107 @@ pub fn record(sim_state: &SimState, p: &Params, recording_state: &mut RecordingS
             Err(e) => panic!("Couldn't write to file: {:?}", e),
         }
         recording_state.last_recorded = new_vals;
+        if let Some(provenance) = &mut recording_state.provenance {
+            provenance.recorded(sim_state.timestep);
+        }
+    }
+    if let Some(snapshots) = &mut recording_state.snapshots {
+        if let Err(e) = snapshots.record(sim_state.timestep, &sim_state.ts) {
+            panic!("Couldn't write to file: {:?}", e)
+        }
+    }
+    match &p.svg {
+        Some(svg) if svg.timesteps.contains(&sim_state.timestep) => {
+            let path = svg_file_path(&p.output_file_path, sim_state.timestep);
+            // sim_state.stitching is from the start of the run, before merges renumbered the nodes
+            let stitching = if svg.options.stitching { Some(stitch_default(&sim_state.ts)) } else { None };
+            if let Err(e) = write_svg(&path, &sim_state.ts, stitching.as_ref(), &svg.options) {
+                println!("Couldn't write {}: {}", path, e);
+            }
+        }
+        _ => {}
+    }
+    if let Some(frames) = &mut recording_state.frames {
+        if let Err(e) = frames.record(sim_state.timestep, &sim_state.ts) {
+            println!("Couldn't write frame {}: {}", sim_state.timestep, e);
+        }
+    }
+    if let Some(thickness) = &mut recording_state.thickness {
+        if let Err(e) = thickness.record(sim_state.timestep, &sim_state.ts) {
+            panic!("Couldn't write to file: {:?}", e)
+        }
+    }
+}
+
+// Only runs that get to their last step come here, so runs that panic or get killed are left without an end time
+fn finish_provenance(recording_state: &RecordingState) {
+    if let Some(provenance) = &recording_state.provenance {
+        provenance.finish();
+    }
+}
+
+pub fn finish_replica_exchange(recording_state: &mut RecordingState) {
+    finish_provenance(recording_state);
+}
+
+// The surface the run ended with always gets a snapshot, a frame and its thicknesses, whatever the intervals, and then
+// the frames become a GIF if they're meant to
+pub fn finish(sim_state: &SimState, recording_state: &mut RecordingState) {
+    if let Some(snapshots) = &mut recording_state.snapshots {
+        if let Err(e) = snapshots.finish(sim_state.timestep, &sim_state.ts) {
+            panic!("Couldn't write to file: {:?}", e)
+        }
+    }
+    if let Some(thickness) = &mut recording_state.thickness {
+        if let Err(e) = thickness.finish(sim_state.timestep, &sim_state.ts) {
+            panic!("Couldn't write to file: {:?}", e)
+        }
+    }
+    if let Some(frames) = &mut recording_state.frames {
+        if let Err(e) = frames.finish(sim_state.timestep, &sim_state.ts) {
+            println!("Couldn't finish the frames: {}", e);
+        }
+    }
+    finish_provenance(recording_state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::default_params;
+    use file_io::provenance::provenance_file_path;
+    use file_io::scratch::ScratchDir;
+
+    fn has_end_time(output_file_path: &str) -> bool {
+        let meta = std::fs::read_to_string(provenance_file_path(output_file_path)).unwrap();
+        meta.parse::<toml::Value>().unwrap().get("end_time").is_some()
+    }
+
+    #[test]
+    fn only_runs_that_finish_get_an_end_time() {
+        let dir = ScratchDir::new("end_time");
+        let mut p = default_params();
+        p.initial_num_points = 20;
+        p.how_smooth = 2;
+        p.recorders = vec![String::from("energy")];
+
+        p.output_file_path = dir.path("finished.csv");
+        let sim_state = SimState::initial_state(&p);
+        let mut recording_state = RecordingState::initial_state(&p).unwrap();
+        record(&sim_state, &p, &mut recording_state);
+        finish(&sim_state, &mut recording_state);
+        assert!(has_end_time(&p.output_file_path));
+
+        // Like a sweep run that panics and gets caught
+        p.output_file_path = dir.path("failed.csv");
+        let failed = p.clone();
+        let caught = std::panic::catch_unwind(move || {
+            let sim_state = SimState::initial_state(&failed);
+            let mut recording_state = RecordingState::initial_state(&failed).unwrap();
+            record(&sim_state, &failed, &mut recording_state);
+            panic!("the run fails");
+        });
+        assert!(caught.is_err());
+        assert!(!has_end_time(&p.output_file_path));
     }
 }
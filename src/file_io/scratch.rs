@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// -----------------------------------
+// A directory of its own for a test to write files to, under the system's temp directory and named after the test,
+// the process and a counter, so tests running at the same time (in this process or another) never share one. It's
+// removed with everything in it when it goes out of scope.
+// -----------------------------------
+pub struct ScratchDir {
+    dir: PathBuf,
+}
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+impl ScratchDir {
+    pub fn new(test_name: &str) -> ScratchDir {
+        let dir = std::env::temp_dir().join(format!(
+            "sars_{}_{}_{}",
+            test_name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        ScratchDir { dir }
+    }
+
+    pub fn path(&self, file_name: &str) -> String {
+        self.dir.join(file_name).to_str().unwrap().to_string()
+    }
+
+    pub fn write(&self, file_name: &str, content: &str) -> String {
+        let path = self.path(file_name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
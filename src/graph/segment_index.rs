@@ -0,0 +1,302 @@
+use graph::types::{Graph, NodeIndex, ThickSurface};
+use linalg_helpers::{dist_to_segment, intersection};
+use std::collections::HashMap;
+
+/* A segment is named by its layer and the node it starts at: (layer, id) is the line from id to id.next */
+pub type SegmentId = (usize, NodeIndex);
+type Cell = (i64, i64);
+
+/* Uniform grid over the segments of a ThickSurface. Every segment is filed under all the cells its bounding box touches,
+so two segments can only cross if they share a cell. Node moves and additions are cheap to keep track of (see `update`);
+anything that renumbers nodes (merges) should just `rebuild`. */
+#[derive(Clone, Debug, Default)]
+pub struct SegmentIndex {
+    cell_size: f64,
+    cells: HashMap<Cell, Vec<SegmentId>>,
+    segment_cells: HashMap<SegmentId, Vec<Cell>>,
+}
+
+fn segment_coords(ts: &ThickSurface, (layer, id): SegmentId) -> (f64, f64, f64, f64) {
+    let g = &ts.layers[layer];
+    let n = &g.nodes[id];
+    let n_next = n.next(g);
+    (n.x, n.y, n_next.x, n_next.y)
+}
+
+fn mean_segment_length(ts: &ThickSurface) -> f64 {
+    let (mut total, mut count) = (0.0, 0);
+    for g in &ts.layers {
+        for n in &g.nodes {
+            let n_next = n.next(g);
+            total = total + (n.x - n_next.x).hypot(n.y - n_next.y);
+            count = count + 1;
+        }
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f64
+    }
+}
+
+/* Segments whose shape depends on where these nodes are: the ones starting and ending at them */
+fn segments_touching<I: IntoIterator<Item = NodeIndex>>(g: &Graph, layer: usize, ids: I) -> Vec<SegmentId> {
+    let mut ret = Vec::new();
+    for id in ids {
+        ret.push((layer, id));
+        ret.push((layer, g.nodes[id].prev_id));
+    }
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+impl SegmentIndex {
+    pub fn new(ts: &ThickSurface) -> SegmentIndex {
+        // A couple of segments per cell keeps the candidate lists short without filing every segment under many cells
+        let cell_size = 2.0 * mean_segment_length(ts);
+        let mut ret = SegmentIndex {
+            cell_size: if cell_size > 0.0 { cell_size } else { 1.0 },
+            cells: HashMap::new(),
+            segment_cells: HashMap::new(),
+        };
+        ret.rebuild(ts);
+        ret
+    }
+
+    pub fn rebuild(&mut self, ts: &ThickSurface) {
+        self.cells.clear();
+        self.segment_cells.clear();
+        for (layer, g) in ts.layers.iter().enumerate() {
+            for n in &g.nodes {
+                self.insert(ts, (layer, n.id));
+            }
+        }
+    }
+
+    /* Re-files every segment that starts or ends at one of `ids`. Call it after moving or adding those nodes. */
+    pub fn update<I: IntoIterator<Item = NodeIndex>>(&mut self, ts: &ThickSurface, layer: usize, ids: I) {
+        for s in segments_touching(&ts.layers[layer], layer, ids) {
+            self.remove(s);
+            self.insert(ts, s);
+        }
+    }
+
+    /* Looks for a crossing involving the segments that start or end at `ids`, which is all that can have changed if the
+    surface had no crossings before those nodes moved */
+    pub fn intersection_near<I: IntoIterator<Item = NodeIndex>>(&self, ts: &ThickSurface, layer: usize, ids: I) -> Option<(f64, f64)> {
+        for s in segments_touching(&ts.layers[layer], layer, ids) {
+            let (x1, y1, x2, y2) = segment_coords(ts, s);
+            for other in self.candidates(x1, y1, x2, y2) {
+                let (x3, y3, x4, y4) = segment_coords(ts, other);
+                match intersection(x1, y1, x2, y2, x3, y3, x4, y4) {
+                    Some(int) => return Some(int),
+                    None => {}
+                }
+            }
+        }
+        None
+    }
+
+    /* Looks for a crossing between the line (x1 y1, x2 y2) and any indexed segment not in `ignoring` */
+    pub fn line_intersection(&self, ts: &ThickSurface, (x1, y1, x2, y2): (f64, f64, f64, f64), ignoring: &[SegmentId]) -> Option<(f64, f64)> {
+        for other in self.candidates(x1, y1, x2, y2) {
+            if ignoring.contains(&other) {
+                continue;
+            }
+            let (x3, y3, x4, y4) = segment_coords(ts, other);
+            match intersection(x1, y1, x2, y2, x3, y3, x4, y4) {
+                Some(int) => return Some(int),
+                None => {}
+            }
+        }
+        None
+    }
+
+    /* Same answer as checking every pair of segments, but only pairs sharing a cell are ever compared */
+    pub fn any_intersection(&self, ts: &ThickSurface) -> Option<(f64, f64)> {
+        for segments in self.cells.values() {
+            for i in 0..segments.len() {
+                let (x1, y1, x2, y2) = segment_coords(ts, segments[i]);
+                for j in i + 1..segments.len() {
+                    let (x3, y3, x4, y4) = segment_coords(ts, segments[j]);
+                    match intersection(x1, y1, x2, y2, x3, y3, x4, y4) {
+                        Some(int) => return Some(int),
+                        None => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /* How far (x, y) is from the closest segment of `layer`. Goes through the cells in growing squares around the point:
+    every segment closer than r cells is filed somewhere in the square of radius r, so once the closest one so far is
+    nearer than that nothing further out can beat it. */
+    pub fn distance_to_layer(&self, ts: &ThickSurface, layer: usize, x: f64, y: f64) -> f64 {
+        let distance = |s: SegmentId| {
+            let (x1, y1, x2, y2) = segment_coords(ts, s);
+            dist_to_segment(x, y, x1, y1, x2, y2)
+        };
+        // Any segment of the layer bounds how far out the search has to go
+        let mut closest = match ts.layers[layer].nodes.first() {
+            Some(n) => distance((layer, n.id)),
+            None => return f64::INFINITY,
+        };
+        let (ci, cj) = self.cell_of(x, y);
+        let mut r = 0;
+        loop {
+            for i in ci - r..ci + r + 1 {
+                for j in cj - r..cj + r + 1 {
+                    if (i - ci).abs() < r && (j - cj).abs() < r {
+                        continue; // <- inside the square, already looked at
+                    }
+                    if let Some(segments) = self.cells.get(&(i, j)) {
+                        for s in segments.iter().filter(|s| s.0 == layer) {
+                            closest = closest.min(distance(*s));
+                        }
+                    }
+                }
+            }
+            if closest <= r as f64 * self.cell_size {
+                return closest;
+            }
+            r += 1;
+        }
+    }
+
+    fn cell_of(&self, x: f64, y: f64) -> Cell {
+        ((x / self.cell_size).floor() as i64, (y / self.cell_size).floor() as i64)
+    }
+
+    fn cells_covering(&self, x1: f64, y1: f64, x2: f64, y2: f64) -> Vec<Cell> {
+        let (min_i, min_j) = self.cell_of(x1.min(x2), y1.min(y2));
+        let (max_i, max_j) = self.cell_of(x1.max(x2), y1.max(y2));
+        let mut ret = Vec::new();
+        for i in min_i..max_i + 1 {
+            for j in min_j..max_j + 1 {
+                ret.push((i, j));
+            }
+        }
+        ret
+    }
+
+    fn candidates(&self, x1: f64, y1: f64, x2: f64, y2: f64) -> Vec<SegmentId> {
+        let mut ret = Vec::new();
+        for c in self.cells_covering(x1, y1, x2, y2) {
+            match self.cells.get(&c) {
+                Some(segments) => ret.extend_from_slice(segments),
+                None => {}
+            }
+        }
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    fn insert(&mut self, ts: &ThickSurface, s: SegmentId) {
+        let (x1, y1, x2, y2) = segment_coords(ts, s);
+        let cells = self.cells_covering(x1, y1, x2, y2);
+        for c in &cells {
+            self.cells.entry(*c).or_insert_with(Vec::new).push(s);
+        }
+        self.segment_cells.insert(s, cells);
+    }
+
+    fn remove(&mut self, s: SegmentId) {
+        match self.segment_cells.remove(&s) {
+            Some(cells) => {
+                for c in cells {
+                    let now_empty = match self.cells.get_mut(&c) {
+                        Some(segments) => {
+                            segments.retain(|other| *other != s);
+                            segments.is_empty()
+                        }
+                        None => false,
+                    };
+                    if now_empty {
+                        self.cells.remove(&c);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::circular_thick_surface;
+    use graph::effects::{apply_changes, random_change, revert_changes, smooth_change_out};
+    use graph::graphs_to_lines;
+    use graph::types::{Smooth, INNER, OUTER};
+    use rand::SeedableRng;
+    use types::SimRng;
+
+    // lines_intersection skips the last line, so compare against plain all-pairs instead
+    fn brute_force_intersects(ts: &ThickSurface) -> bool {
+        let lines = graphs_to_lines(&ts.layers);
+        for i in 0..lines.len() {
+            for j in i + 1..lines.len() {
+                let ((x1, y1, x2, y2), (x3, y3, x4, y4)) = (lines[i], lines[j]);
+                if intersection(x1, y1, x2, y2, x3, y3, x4, y4).is_some() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn index_agrees_with_checking_every_pair() {
+        let mut rng = SimRng::seed_from_u64(3);
+        let mut ts = circular_thick_surface(1.0, 0.3, 40);
+        let mut index = SegmentIndex::new(&ts);
+        let (mut crossed, mut clean) = (0, 0);
+        for _ in 0..300 {
+            let layer = if crossed % 2 == 0 { OUTER } else { INNER };
+            let change = random_change(&ts.layers[layer], (-0.4, 0.4), &mut rng);
+            let change = smooth_change_out(&ts.layers[layer], change, Smooth::Count(3));
+            apply_changes(&mut ts.layers[layer], &change);
+            index.update(&ts, layer, change.unwrap().keys().cloned());
+
+            let expected = brute_force_intersects(&ts);
+            assert_eq!(index.intersection_near(&ts, layer, change.unwrap().keys().cloned()).is_some(), expected);
+            assert_eq!(index.any_intersection(&ts).is_some(), expected);
+            assert_eq!(SegmentIndex::new(&ts).any_intersection(&ts).is_some(), expected);
+
+            if expected {
+                crossed = crossed + 1;
+                revert_changes(&mut ts.layers[layer], &change);
+                index.update(&ts, layer, change.unwrap().keys().cloned());
+            } else {
+                clean = clean + 1;
+            }
+        }
+        assert!(crossed > 0 && clean > 0);
+    }
+
+    #[test]
+    fn distance_to_a_layer_is_to_its_closest_segment() {
+        let mut rng = SimRng::seed_from_u64(5);
+        let mut ts = circular_thick_surface(1.0, 0.3, 40);
+        for layer in &[OUTER, INNER] {
+            let change = smooth_change_out(&ts.layers[*layer], random_change(&ts.layers[*layer], (-0.2, 0.2), &mut rng), Smooth::Count(5));
+            apply_changes(&mut ts.layers[*layer], &change);
+        }
+        let index = SegmentIndex::new(&ts);
+        // The outer nodes, plus a point in the middle and one well outside the surface
+        let mut points: Vec<(f64, f64)> = ts.layers[OUTER].nodes.iter().map(|n| (n.x, n.y)).collect();
+        points.extend_from_slice(&[(0.0, 0.0), (3.0, 0.5)]);
+        for (x, y) in points {
+            let expected = ts.layers[INNER]
+                .nodes
+                .iter()
+                .map(|i| segment_coords(&ts, (INNER, i.id)))
+                .map(|(x1, y1, x2, y2)| dist_to_segment(x, y, x1, y1, x2, y2))
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(index.distance_to_layer(&ts, INNER, x, y), expected);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use graph::types::{Node, ThickSurface, INNER, OUTER};
+use graph::segment_index::SegmentIndex;
+use graph::{distance_between_nodes, walk};
+use stitcher::types::{ListMap, Stitching};
+
+// -----------------------------------
+// How thick the gray matter is at every outer node, in walking order. Two ways to measure it: the distance to the
+// closest point of the inner surface, or, with a stitching, the distance to the closest inner node the node is
+// stitched to, which is what the pushes treat as the node's thickness.
+// -----------------------------------
+
+// None if the node isn't stitched to any inner node that still exists
+fn to_closest_correspondent(ts: &ThickSurface, stitching: &Stitching, n: &Node) -> Option<f64> {
+    let Stitching::Stitch(layers) = stitching;
+    let ListMap::LMap(correspondences) = &layers[OUTER];
+    // A stitching can be older than the surface, so nodes it mentions may be gone
+    correspondences
+        .get(&n.id)?
+        .iter()
+        .filter_map(|(inner_id, _, _)| ts.layers[INNER].nodes.get(*inner_id))
+        .map(|i| distance_between_nodes(n, i))
+        .fold(None, |closest: Option<f64>, d| Some(closest.map_or(d, |c| c.min(d))))
+}
+
+// Outer nodes the stitching doesn't cover fall back on the nearest inner segment
+pub fn local_thickness(ts: &ThickSurface, stitching: Option<&Stitching>) -> Vec<f64> {
+    let index = SegmentIndex::new(ts);
+    walk(&ts.layers[OUTER])
+        .iter()
+        .map(|n| match stitching.and_then(|s| to_closest_correspondent(ts, s, n)) {
+            Some(d) => d,
+            None => index.distance_to_layer(ts, INNER, n.x, n.y),
+        })
+        .collect()
+}
+
+// (mean, standard deviation, min, max)
+pub fn thickness_stats(thicknesses: &[f64]) -> (f64, f64, f64, f64) {
+    let n = thicknesses.len() as f64;
+    let mean = thicknesses.iter().sum::<f64>() / n;
+    let variance = thicknesses.iter().map(|t| (t - mean) * (t - mean)).sum::<f64>() / n;
+    let min = thicknesses.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = thicknesses.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    (mean, variance.sqrt(), min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{circular_thick_surface, cyclic_graph_from_coords};
+    use stitcher::stitch_default;
+
+    #[test]
+    fn a_circle_is_equally_thick_everywhere() {
+        let ts = circular_thick_surface(1.0, 0.2, 40);
+        let stitching = stitch_default(&ts);
+        for thicknesses in &[local_thickness(&ts, None), local_thickness(&ts, Some(&stitching))] {
+            assert_eq!(thicknesses.len(), 40);
+            let (mean, std, min, max) = thickness_stats(thicknesses);
+            // The nearest inner segment is a little closer than the inner nodes, which are 0.2 away
+            assert!(mean <= 0.2 + 1e-9 && mean > 0.19, "{}", mean);
+            assert!(std < 1e-9 && max - min < 1e-9);
+        }
+    }
+
+    #[test]
+    fn stitched_thickness_survives_nodes_that_are_gone() {
+        let ts = circular_thick_surface(1.0, 0.2, 12);
+        let stitching = stitch_default(&ts);
+        // Same outer ring, an inner ring with fewer nodes than the stitching refers to
+        let fewer: Vec<(f64, f64)> = ts.layers[INNER].to_vec_of_points().into_iter().take(6).collect();
+        let shrunk = ThickSurface::new(ts.layers[OUTER].clone(), cyclic_graph_from_coords(&fewer));
+        let thicknesses = local_thickness(&shrunk, Some(&stitching));
+        assert_eq!(thicknesses.len(), 12);
+        assert!(thicknesses.iter().all(|t| t.is_finite() && *t > 0.0));
+    }
+}
@@ -97,7 +97,7 @@ pub struct NodeAddition {
     pub n: Node,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Node {
     pub id: NodeIndex,
     pub x: f64,
@@ -137,7 +137,7 @@ pub struct NodeChange {
     pub delta_y: f64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Graph {
     pub nodes: Vec<Node>,
 }
@@ -167,7 +167,7 @@ impl Graph {
 
 pub const OUTER: usize = 0;
 pub const INNER: usize = 1;
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThickSurface {
     pub layers: Vec<Graph>,
 }
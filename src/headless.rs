@@ -0,0 +1,40 @@
+use energy::{energy, energy_terms};
+use file_io::checkpoint;
+use file_io::recorders;
+use file_io::recorders::RecordingState;
+use graph::area;
+use graph::types::OUTER;
+use optimizer;
+use simulated_annealing::SimState;
+use types::Params;
+
+// -----------------------------------
+// The loop behind every run without a window: `run`, `resume`, `--replicas` and each run of a sweep. It records,
+// steps and checkpoints until `how_many_reps`, printing how it's going every 500 steps if `print_progress` is set
+// (sweeps don't, since their runs would all print over each other).
+// -----------------------------------
+pub fn run_headless(sim_state: &mut SimState, params: &Params, recording_state: &mut RecordingState, how_many_reps: u64, print_progress: bool) {
+    loop {
+        recorders::record(sim_state, params, recording_state);
+        optimizer::step(sim_state, params);
+        if checkpoint::should_checkpoint(sim_state, params) {
+            if let Err(e) = checkpoint::save(sim_state, params) {
+                println!("Couldn't write checkpoint at timestep {}: {}", sim_state.timestep, e);
+            }
+        }
+        if print_progress && sim_state.timestep % 500 == 0 {
+            let terms: Vec<String> = energy_terms(&sim_state.ts, params).iter().map(|(n, v)| format!("{}: {}", n, v)).collect();
+            println!(
+                "Timestep {}: energy: {} ({}), outer area: {}...",
+                sim_state.timestep,
+                energy(&sim_state.ts, params),
+                terms.join(", "),
+                area(&sim_state.ts.layers[OUTER])
+            )
+        }
+        if sim_state.timestep > how_many_reps { // Não sei de onde tirar esse número
+            break;
+        }
+    }
+    recorders::finish(sim_state, recording_state);
+}
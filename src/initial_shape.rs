@@ -0,0 +1,249 @@
+use file_io::mask_import::read_mask;
+use file_io::surface::read_surfaces;
+use file_io::svg_import::read_contours;
+use graph::types::{Graph, ThickSurface, INNER, OUTER};
+use graph::{area, circular_thick_surface, cyclic_graph_from_coords, graphs_to_lines, gray_matter_area};
+use linalg_helpers::{lines_intersection, point_in_ring};
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+use types::{Params, SimRng};
+
+// -----------------------------------
+// What the surface looks like at timestep 0, chosen in the parameters file with e.g.
+// initial_shape = {kind = "ellipse", semi_axes = [0.95, 0.6]}
+// Generated shapes have initial_num_points nodes per layer, the inner one initial_thickness inside the outer one.
+// A file brings its own rings. Either way the two rings can't cross themselves or each other.
+// -----------------------------------
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InitialShape {
+    Circle,                                                // radius initial_radius
+    Ellipse { semi_axes: (f64, f64) },                     // along x and y
+    Superellipse { semi_axes: (f64, f64), exponent: f64 }, // |x/a|^n + |y/b|^n = 1, closer to a rectangle the larger n is
+    PerturbedCircle { amplitude: f64, harmonics: usize },  // initial_radius * (1 + noise), |noise| <= amplitude, from the seed
+    File { path: String, timestep: Option<u64> },          // a surface CSV (see file_io::surface), its last timestep by default
+    Svg { path: String, ids: Option<(String, String)> },   // two closed paths (see file_io::svg_import), by (outer, inner) id or in order
+    Mask { path: String, invert: bool },                   // the cortex in white in a PNG (see file_io::mask_import), in black with invert
+}
+
+type Ring = Vec<(f64, f64)>;
+
+impl InitialShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InitialShape::Circle => "circle",
+            InitialShape::Ellipse { .. } => "ellipse",
+            InitialShape::Superellipse { .. } => "superellipse",
+            InitialShape::PerturbedCircle { .. } => "perturbed_circle",
+            InitialShape::File { .. } => "file",
+            InitialShape::Svg { .. } => "svg",
+            InitialShape::Mask { .. } => "mask",
+        }
+    }
+
+    // (outer, inner), in whatever direction they come in
+    fn rings(&self, p: &Params) -> Result<(Ring, Ring), String> {
+        let n = p.initial_num_points;
+        let t = p.initial_thickness;
+        let angle = |i: usize| i as f64 * 2.0 * PI / n as f64;
+        Ok(match self {
+            InitialShape::Circle => {
+                let ts = circular_thick_surface(p.initial_radius, t, n);
+                (open_ring(&ts.layers[OUTER]), open_ring(&ts.layers[INNER]))
+            }
+            InitialShape::Ellipse { semi_axes } => {
+                let ellipse = |(a, b): (f64, f64)| (0..n).map(|i| (a * angle(i).cos(), b * angle(i).sin())).collect();
+                (ellipse(*semi_axes), ellipse(inset(*semi_axes, t)?))
+            }
+            InitialShape::Superellipse { semi_axes, exponent } => {
+                if *exponent <= 0.0 {
+                    return Err(format!("exponent ({}) should be positive", exponent));
+                }
+                let power = |c: f64| c.signum() * c.abs().powf(2.0 / exponent);
+                let superellipse = |(a, b): (f64, f64)| (0..n).map(|i| (a * power(angle(i).cos()), b * power(angle(i).sin()))).collect();
+                (superellipse(*semi_axes), superellipse(inset(*semi_axes, t)?))
+            }
+            InitialShape::PerturbedCircle { amplitude, harmonics } => {
+                // Waves of 2 to harmonics + 1 bumps around, with random weights and phases adding up to at most amplitude
+                let mut rng = SimRng::seed_from_u64(p.seed);
+                let waves: Vec<(f64, f64)> = (0..*harmonics)
+                    .map(|_| (rng.gen_range(-1.0, 1.0) * amplitude / *harmonics as f64, rng.gen_range(0.0, 2.0 * PI)))
+                    .collect();
+                let radius = |theta: f64| {
+                    let noise: f64 = waves
+                        .iter()
+                        .enumerate()
+                        .map(|(k, (w, phase))| w * ((k + 2) as f64 * theta + phase).sin())
+                        .sum();
+                    p.initial_radius * (1.0 + noise)
+                };
+                let ring = |inward: f64| {
+                    (0..n)
+                        .map(|i| ((radius(angle(i)) - inward) * angle(i).cos(), (radius(angle(i)) - inward) * angle(i).sin()))
+                        .collect()
+                };
+                (ring(0.0), ring(t))
+            }
+            InitialShape::File { path, timestep } => {
+                let surfaces = read_surfaces(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+                let found = match timestep {
+                    Some(t) => surfaces.into_iter().find(|(st, _)| st == t),
+                    None => surfaces.into_iter().last(),
+                };
+                match (found, timestep) {
+                    (Some((_, ts)), _) => (open_ring(&ts.layers[OUTER]), open_ring(&ts.layers[INNER])),
+                    (None, Some(t)) => return Err(format!("{} has no surface at timestep {}", path, t)),
+                    (None, None) => return Err(format!("{} has no surfaces", path)),
+                }
+            }
+            InitialShape::Svg { path, ids } => {
+                // Nodes as far apart as they can be without node addition splitting every segment right away
+                let ids = ids.as_ref().map(|(outer, inner)| (outer.as_str(), inner.as_str()));
+                let ts = read_contours(path, ids, p.node_addition_threshold).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+                (open_ring(&ts.layers[OUTER]), open_ring(&ts.layers[INNER]))
+            }
+            InitialShape::Mask { path, invert } => {
+                let ts = read_mask(path, n, *invert).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+                (open_ring(&ts.layers[OUTER]), open_ring(&ts.layers[INNER]))
+            }
+        })
+    }
+}
+
+fn inset((a, b): (f64, f64), thickness: f64) -> Result<(f64, f64), String> {
+    if thickness >= a.min(b) {
+        return Err(format!(
+            "initial_thickness ({}) should be smaller than both semi_axes ({}, {})",
+            thickness, a, b
+        ));
+    }
+    Ok((a - thickness, b - thickness))
+}
+
+fn open_ring(g: &Graph) -> Ring {
+    let mut ret = g.to_vec_of_points();
+    ret.pop(); // <- to_vec_of_points closes the ring by repeating the first node
+    ret
+}
+
+// Counterclockwise, like circular_points, so areas come out positive
+fn counterclockwise(ring: Ring) -> Graph {
+    let g = cyclic_graph_from_coords(&ring);
+    if area(&g) >= 0.0 {
+        return g;
+    }
+    let mut ring = ring;
+    ring.reverse();
+    cyclic_graph_from_coords(&ring)
+}
+
+pub fn thick_surface(p: &Params) -> Result<ThickSurface, String> {
+    let (outer, inner) = p.initial_shape.rings(p)?;
+    if outer.len() < 3 || inner.len() < 3 {
+        return Err(format!(
+            "both rings need at least 3 nodes, got {} outer and {} inner",
+            outer.len(),
+            inner.len()
+        ));
+    }
+    let ts = ThickSurface::new(counterclockwise(outer.clone()), counterclockwise(inner.clone()));
+    if let Some((x, y)) = lines_intersection(&graphs_to_lines(&ts.layers)) {
+        return Err(format!("the surface crosses itself at ({}, {})", x, y));
+    }
+    // Rings that don't cross are either one inside the other or apart, so one inner node tells which
+    if !point_in_ring(&outer, inner[0].0, inner[0].1) {
+        return Err(String::from("the inner ring should be inside the outer one"));
+    }
+    Ok(ts)
+}
+
+// The same parameters with another seed. A perturbed circle comes out different for every seed, so the gray matter
+// area gray_matter_stretch compares against is worked out again from the shape this seed gives
+pub fn reseeded(p: &Params, seed: u64) -> Result<Params, String> {
+    let mut ret = Params { seed, ..p.clone() };
+    ret.initial_gray_matter_area = gray_matter_area(&thick_surface(&ret)?);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use file_io::{defaults_table, params_from_toml, ParamsError};
+    use linalg_helpers::circular_points;
+    use simulated_annealing::SimState;
+
+    fn params(shape: &str) -> Result<Params, ParamsError> {
+        let mut t = defaults_table();
+        t.insert(
+            String::from("initial_shape"),
+            format!("x = {}", shape).parse::<toml::Value>().unwrap()["x"].clone(),
+        );
+        params_from_toml(toml::Value::Table(t), "parameters.toml")
+    }
+
+    #[test]
+    fn generated_shapes_are_valid_and_have_their_own_area() {
+        let circle = params("{kind = \"circle\"}").unwrap();
+        for shape in &[
+            "{kind = \"ellipse\", semi_axes = [0.9, 0.5]}",
+            "{kind = \"superellipse\", semi_axes = [0.9, 0.5], exponent = 4.0}",
+            "{kind = \"perturbed_circle\", amplitude = 0.1}",
+        ] {
+            let p = params(shape).unwrap();
+            let ts = thick_surface(&p).unwrap();
+            assert_eq!(ts.layers[OUTER].nodes.len(), p.initial_num_points);
+            assert_eq!(p.initial_gray_matter_area, gray_matter_area(&ts));
+            // A perturbed circle's bumps and dents cancel out in its areas, give or take rounding, so it's told apart by its nodes
+            let circle_nodes = thick_surface(&circle).unwrap().layers[OUTER].to_vec_of_points();
+            assert!(ts.layers[OUTER].to_vec_of_points() != circle_nodes, "{}", shape);
+        }
+    }
+
+    #[test]
+    fn another_seed_gets_the_area_of_its_own_shape() {
+        let p = params("{kind = \"perturbed_circle\", amplitude = 0.1}").unwrap();
+        let other = reseeded(&p, p.seed ^ 1).unwrap();
+        // What the simulation starts from, like after --seed
+        let ts = SimState::initial_state(&other).ts;
+        assert!(ts.layers[OUTER].to_vec_of_points() != thick_surface(&p).unwrap().layers[OUTER].to_vec_of_points());
+        assert_eq!(other.initial_gray_matter_area, gray_matter_area(&ts));
+    }
+
+    // A surface file with the two rings
+    fn rings_file(dir: &ScratchDir, outer: Vec<(f64, f64)>, inner: Vec<(f64, f64)>) -> String {
+        let mut csv = String::from("timestep,layer,x,y\n");
+        for (layer, ring) in [outer, inner].iter().enumerate() {
+            for (x, y) in ring {
+                csv.push_str(&format!("0,{},{},{}\n", layer, x, y));
+            }
+        }
+        dir.write("rings.csv", &csv)
+    }
+
+    fn rejection(path: &str) -> String {
+        match params(&format!("{{kind = \"file\", path = \"{}\"}}", path)) {
+            Err(ParamsError::Inconsistent { reason, .. }) => reason,
+            _ => panic!("{} should have been rejected", path),
+        }
+    }
+
+    #[test]
+    fn crossed_rings_are_rejected() {
+        let dir = ScratchDir::new("crossed_shape");
+        // The inner ring sticks out of the outer one
+        let path = rings_file(&dir, circular_points(0.0, 0.0, 1.0, 20), circular_points(0.5, 0.0, 0.6, 20));
+        let reason = rejection(&path);
+        assert!(reason.contains("crosses"), "{}", reason);
+        assert!(params("{kind = \"ellipse\", semi_axes = [0.9, 0.02]}").is_err());
+        // <- thinner than initial_thickness
+    }
+
+    #[test]
+    fn the_inner_ring_has_to_be_inside_the_outer_one() {
+        let dir = ScratchDir::new("apart_shape");
+        // Smaller, but next to it rather than in it
+        let path = rings_file(&dir, circular_points(0.0, 0.0, 1.0, 20), circular_points(3.0, 0.0, 0.5, 20));
+        let reason = rejection(&path);
+        assert!(reason.contains("inside the outer one"), "{}", reason);
+    }
+}
@@ -52,8 +52,38 @@ fn cross_product(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
     x1 * y2 - y1 * x2
 }
 
+/* Signed angle by which the direction of travel turns at (x2, y2) when walking (x1, y1) -> (x2, y2) -> (x3, y3).
+Positive for counter-clockwise turns, in the range [-pi, pi]. */
+pub fn turning_angle(x1: f64, y1: f64, x2: f64, y2: f64, x3: f64, y3: f64) -> f64 {
+    let (ax, ay, bx, by) = (x2 - x1, y2 - y1, x3 - x2, y3 - y2);
+    cross_product(ax, ay, bx, by).atan2(ax * bx + ay * by)
+}
+
+/* Distance from (px, py) to the closest point of the segment (x1 y1, x2 y2) */
+pub fn dist_to_segment(px: f64, py: f64, x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return dist(px, py, x1, y1);
+    }
+    let t = (((px - x1) * dx + (py - y1) * dy) / length_squared).max(0.0).min(1.0);
+    dist(px, py, x1 + t * dx, y1 + t * dy)
+}
+
+/* Whether (x, y) is inside the closed ring, by even-odd ray casting */
+pub fn point_in_ring(ring: &[(f64, f64)], x: f64, y: f64) -> bool {
+    let mut ret = false;
+    for i in 0..ring.len() {
+        let ((x1, y1), (x2, y2)) = (ring[i], ring[(i + 1) % ring.len()]);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            ret = !ret;
+        }
+    }
+    ret
+}
+
 /* Returns potential intersection between lines (x1 y1, x2 y2) and (x3 y3, x4 y4) */
-fn intersection(x1: f64, y1: f64, x2: f64, y2: f64, x3: f64, y3: f64, x4: f64, y4: f64) -> Option<(f64, f64)> {
+pub fn intersection(x1: f64, y1: f64, x2: f64, y2: f64, x3: f64, y3: f64, x4: f64, y4: f64) -> Option<(f64, f64)> {
     let (rx, ry, sx, sy) = (x2 - x1, y2 - y1, x4 - x3, y4 - y3);
 
     /* Now we have: line = q + qv, and any point on the vector is obtainable by p + t*r, for some t
@@ -164,4 +194,23 @@ mod tests {
             None => assert!(true),
         }
     }
+
+    #[test]
+    fn distance_to_a_segment_is_to_its_closest_point() {
+        assert_eq!(dist_to_segment(0.5, 2.0, 0.0, 0.0, 1.0, 0.0), 2.0);
+        /* Past the ends it's the distance to the nearest end */
+        assert_eq!(dist_to_segment(4.0, 4.0, 0.0, 0.0, 1.0, 0.0), 5.0);
+        assert_eq!(dist_to_segment(3.0, 4.0, 0.0, 0.0, 0.0, 0.0), 5.0);
+    }
+
+    #[test]
+    fn points_are_in_a_ring_only_inside_it() {
+        let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        assert!(point_in_ring(&square, 1.0, 1.0));
+        assert!(!point_in_ring(&square, 3.0, 1.0));
+        assert!(!point_in_ring(&square, 1.0, -1.0));
+        /* Either way around */
+        let reversed: Vec<(f64, f64)> = square.iter().rev().cloned().collect();
+        assert!(point_in_ring(&reversed, 1.0, 1.0));
+    }
 }
@@ -1,16 +1,25 @@
 #![recursion_limit = "256"]
 
+mod cli;
+mod cooling;
+mod energy;
 mod file_io;
 mod graph;
+mod headless;
+mod initial_shape;
 mod linalg_helpers;
 mod my_gui;
+mod optimizer;
+mod parallel_tempering;
 mod renderer;
 mod shared_shit;
 mod simulated_annealing;
 mod simulated_annealing_dumber_and_better;
 mod stitcher;
+mod sweep;
 mod types;
 
+extern crate clap;
 extern crate float_cmp;
 extern crate glutin_window;
 extern crate graphics;
@@ -18,6 +27,7 @@ extern crate opengl_graphics;
 extern crate pathfinding;
 extern crate piston;
 extern crate rand;
+extern crate rand_pcg;
 extern crate toml;
 extern crate vec1;
 
@@ -25,23 +35,68 @@ extern crate conrod_core;
 extern crate conrod_piston;
 extern crate find_folder;
 extern crate geo;
+extern crate gif;
+extern crate image;
 extern crate num_traits;
 extern crate piston_window;
 extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate xml;
 
+use clap::ArgMatches;
 use renderer::draw_mode::draw_mode_rendering;
-use std::env;
-use simulated_annealing::energy;
-use graph::area;
-use graph::types::OUTER;
-use file_io::recorders;
+use file_io::{checkpoint, recorders, summary};
 use toml::from_str;
 
-fn real_main() {
-    let params: types::Params = match std::fs::read_to_string("parameters.toml") {
-        Err(_) => panic!("No parameters.toml file found in directory"),
-        Ok(content) => file_io::toml_table_to_params(content.parse::<toml::Value>().unwrap()),
-    };
+// Misuse and bad files get a message saying what's wrong instead of a panic
+fn exit_with(msg: String) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1)
+}
+
+fn params_or_exit(params_file_path: &str, overrides: &[String]) -> types::Params {
+    match file_io::params_from_file_with_overrides(params_file_path, overrides) {
+        Ok(p) => p,
+        Err(e) => exit_with(e.to_string()),
+    }
+}
+
+fn reseeded_or_exit(params: &types::Params, seed: u64) -> types::Params {
+    match initial_shape::reseeded(params, seed) {
+        Ok(p) => p,
+        Err(e) => exit_with(format!("initial_shape with seed {}: {}", seed, e)),
+    }
+}
+
+// --params, --set, then --output and --seed on top
+fn params_from_matches(m: &ArgMatches) -> types::Params {
+    let overrides: Vec<String> = m.values_of("set").map(|vs| vs.map(String::from).collect()).unwrap_or_default();
+    let mut params = params_or_exit(m.value_of("params").unwrap(), &overrides);
+    if let Some(o) = m.value_of("output") {
+        params.output_file_path = String::from(o);
+    }
+    if let Some(seed) = m.value_of("seed") {
+        params = reseeded_or_exit(&params, seed.parse::<u64>().unwrap());
+    }
+    params
+}
+
+// Only for arguments clap already checked are numbers
+fn unsigned_arg(m: &ArgMatches, name: &str) -> Option<u64> {
+    m.value_of(name).map(|v| v.parse::<u64>().unwrap())
+}
+
+// Next to the CSV, so whatever the layers and --sets added up to can be looked at (and rerun) later
+fn write_effective_params(params: &types::Params) {
+    if let Err(e) = file_io::write_effective_params(params) {
+        println!("Couldn't write the parameters used to {}: {}", file_io::effective_params_file_path(&params.output_file_path), e);
+    }
+}
+
+fn real_main(params: types::Params) {
     let (mut renderer, mut window) = renderer::setup_renderer();
     let mut sim_state = simulated_annealing::SimState::initial_state(&params);
 
@@ -54,28 +109,209 @@ fn real_main() {
     )
 }
 
-fn no_gui_main(params_file_path: &str, how_many_reps: u64) {
-    let params: types::Params = match std::fs::read_to_string(params_file_path) {
-        Err(_) => panic!(format!("Parameter file named \"{}\" not found.", params_file_path)),
-        Ok(content) => file_io::toml_table_to_params(content.parse::<toml::Value>().unwrap()),
-    };
+fn no_gui_main(params: types::Params, how_many_reps: u64) {
+    write_effective_params(&params);
     let mut sim_state = simulated_annealing::SimState::initial_state(&params);
     let mut recording_state = recorders::RecordingState::initial_state(&params).unwrap_or_else(|| {panic!("Couldn't create recording state")});
+    headless::run_headless(&mut sim_state, &params, &mut recording_state, how_many_reps, true)
+}
+
+// Same parameters, seeds seed, seed + 1, ..., each replica writing its own CSV, plus a summary of them all
+fn replicas_main(params: types::Params, how_many_reps: u64, replicas: usize) {
+    let replica_params: Vec<types::Params> = (0..replicas)
+        .map(|i| types::Params {
+            output_file_path: summary::replica_file_path(&params.output_file_path, i),
+            ..reseeded_or_exit(&params, types::nth_seed(params.seed, i as u64))
+        })
+        .collect();
+    sweep::run_in_parallel(replica_params.clone(), sweep::available_threads(), how_many_reps);
+
+    let mut runs = Vec::new();
+    for p in &replica_params {
+        match summary::read_recorded_run(&p.output_file_path) {
+            Ok(r) => runs.push(r),
+            Err(e) => println!("Leaving {} out of the summary: {}", p.output_file_path, e),
+        }
+    }
+    if runs.is_empty() {
+        return;
+    }
+    let seeds: Vec<String> = replica_params.iter().map(|p| p.seed.to_string()).collect();
+    let summary_file_path = summary::summary_file_path(&params.output_file_path);
+    match summary::write_summary(&summary_file_path, &runs, &format!("# replicas: {}\n# seeds: {}\n", runs.len(), seeds.join(" "))) {
+        Ok(_) => println!("Summary of {} replicas written to {}", runs.len(), summary_file_path),
+        Err(e) => println!("Couldn't write summary to {}: {}", summary_file_path, e),
+    }
+}
+
+fn resume_main(checkpoint_file_path: &str, how_many_reps: u64) {
+    let checkpoint::Checkpoint { params, mut sim_state } = match checkpoint::load(checkpoint_file_path) {
+        Err(e) => exit_with(format!("Couldn't load checkpoint \"{}\": {}", checkpoint_file_path, e)),
+        Ok(c) => c,
+    };
+    println!("Resuming from timestep {}...", sim_state.timestep);
+    let mut recording_state = recorders::RecordingState::resume_state(&params, sim_state.timestep).unwrap_or_else(|| {panic!("Couldn't create recording state")});
+    headless::run_headless(&mut sim_state, &params, &mut recording_state, how_many_reps, true)
+}
+
+fn tempering_main(params: types::Params, how_many_reps: u64) {
+    let tempering_params = match &params.parallel_tempering {
+        Some(tp) => tp.clone(),
+        None => exit_with(String::from(
+            "--tempering needs parallel_tempering = {replicas = ..., max_temperature = ..., swap_interval = ...} in the parameters",
+        )),
+    };
+    write_effective_params(&params);
+    let mut re = parallel_tempering::ReplicaExchange::initial_state(&params, &tempering_params);
+    let mut recording_state = recorders::RecordingState::replica_exchange_state(&params, &re).unwrap_or_else(|| panic!("Couldn't create recording state"));
     loop {
-        recorders::record(&sim_state, &params, &mut recording_state);
-        simulated_annealing_dumber_and_better::step(&mut sim_state, &params);
-        if sim_state.timestep % 500 == 0 {
-            println!(
-                "Timestep {}: energy: {}, outer area: {}...",
-                sim_state.timestep,
-                energy(&sim_state.ts, params.initial_gray_matter_area),
-                area(&sim_state.ts.layers[OUTER])
-            )
+        recorders::record_replica_exchange(&re, &params, &mut recording_state);
+        re.step(&params);
+        if re.timestep % 500 == 0 {
+            println!("Timestep {}: swap acceptance rates: {:?}", re.timestep, re.swap_acceptance_rates());
         }
-        if sim_state.timestep > how_many_reps { // Não sei de onde tirar esse número
+        if re.timestep > how_many_reps {
             break;
         }
     }
+    recorders::finish_replica_exchange(&mut recording_state);
+}
+
+fn sweep_main(spec_file_path: &str, overrides: &[String], how_many_reps: u64, output_directory: Option<&str>, threads: Option<u64>) {
+    let mut spec = match std::fs::read_to_string(spec_file_path) {
+        Err(e) => exit_with(format!("{}: couldn't read sweep file: {}", spec_file_path, e)),
+        Ok(content) => match content.parse::<toml::Value>() {
+            Ok(toml::Value::Table(t)) => t,
+            Ok(_) => exit_with(format!("{}: no key-value table found", spec_file_path)),
+            Err(e) => exit_with(format!("{}: {}", spec_file_path, e)),
+        },
+    };
+    if let Some(d) = output_directory {
+        spec.insert(String::from("output_directory"), toml::Value::String(String::from(d)));
+    }
+    if let Some(t) = threads {
+        spec.insert(String::from("threads"), toml::Value::Integer(t as i64));
+    }
+    let spec = toml::Value::Table(spec);
+    // Like "extends", `base` is relative to the file naming it, so sweeps run the same from any directory
+    let base_file_path = file_io::relative_to(spec_file_path, spec.get("base").and_then(|b| b.as_str()).unwrap_or("parameters.toml"));
+    let base = file_io::layered_table(&base_file_path, overrides);
+    let sweep = match base.and_then(|base| sweep::Sweep::from_toml(&spec, spec_file_path, &base, &base_file_path)) {
+        Ok(sweep) => sweep,
+        Err(e) => exit_with(e.to_string()),
+    };
+    if let Err(e) = std::fs::create_dir_all(&sweep.output_directory).and_then(|_| sweep.write_manifest()) {
+        panic!("Couldn't write the manifest to \"{}\": {}", sweep.output_directory, e)
+    }
+    println!("Sweeping {} runs on {} threads into {}...", sweep.runs.len(), sweep.threads, sweep.output_directory);
+    sweep.run(how_many_reps)
+}
+
+// Prints the last timestep's stats, or writes every timestep's to `output`
+fn analyze_main(csv_file_paths: Vec<&str>, output: Option<&str>) {
+    let mut runs = Vec::new();
+    for path in &csv_file_paths {
+        match summary::read_recorded_run(path) {
+            Ok(r) => runs.push(r),
+            Err(e) => exit_with(format!("Couldn't read {}: {}", path, e)),
+        }
+    }
+    match output {
+        Some(o) => match summary::write_summary(o, &runs, &format!("# runs: {}\n", csv_file_paths.join(" "))) {
+            Ok(_) => println!("Summary of {} runs written to {}", runs.len(), o),
+            Err(e) => exit_with(format!("Couldn't write summary to {}: {}", o, e)),
+        },
+        None => match summary::summarize(&runs).map(|s| s.into_iter().last()) {
+            Err(e) => exit_with(e.to_string()),
+            Ok(Some((t, stats_at_t))) => {
+                println!("Timestep {}, over the {} runs that got there:", t, runs.iter().filter(|r| r.rows.contains_key(&t)).count());
+                for (c, s) in runs[0].columns.iter().zip(stats_at_t) {
+                    println!("    {}: mean {}, std {}, min {}, max {}", c, s.mean, s.std, s.min, s.max);
+                }
+            }
+            Ok(None) => println!("Nothing recorded"),
+        },
+    }
+}
+
+// The surface in a checkpoint, or the one at `timestep` (the last one if None) in a snapshots file
+fn load_surface(file_path: &str, timestep: Option<u64>) -> (u64, graph::types::ThickSurface) {
+    if file_path.ends_with(".json") {
+        return match checkpoint::load(file_path) {
+            Err(e) => exit_with(format!("Couldn't load checkpoint \"{}\": {}", file_path, e)),
+            Ok(c) => (c.sim_state.timestep, c.sim_state.ts),
+        };
+    }
+    let snapshots = match file_io::surface::read_surfaces(file_path) {
+        Err(e) => exit_with(format!("Couldn't load snapshots \"{}\": {}", file_path, e)),
+        Ok(s) => s,
+    };
+    let found = match timestep {
+        Some(t) => snapshots.into_iter().find(|(st, _)| *st == t),
+        None => snapshots.into_iter().last(),
+    };
+    match (found, timestep) {
+        (Some(s), _) => s,
+        (None, Some(t)) => exit_with(format!("{} has no snapshot at timestep {}", file_path, t)),
+        (None, None) => exit_with(format!("{} has no snapshots", file_path)),
+    }
+}
+
+fn export_main(input_file_path: &str, timestep: Option<u64>, output: &str, svg_options: renderer::svg::SvgOptions, size: u32) {
+    // Every snapshot in the file, one frame each
+    if output.ends_with(".gif") {
+        let snapshots = match file_io::surface::read_surfaces(input_file_path) {
+            Err(e) => exit_with(format!("Couldn't load snapshots \"{}\": {}", input_file_path, e)),
+            Ok(s) => s,
+        };
+        let count = snapshots.len();
+        let frames = snapshots.iter().map(|(_, ts)| Ok(renderer::raster::frame_of(ts, size, size)));
+        match renderer::raster::write_gif(output, frames, renderer::raster::DEFAULT_GIF_DELAY) {
+            Ok(_) => println!("GIF of {} snapshot(s) written to {}", count, output),
+            Err(e) => exit_with(format!("Couldn't write {}: {}", output, e)),
+        }
+        return;
+    }
+    let (timestep, ts) = load_surface(input_file_path, timestep);
+    let written = if output.ends_with(".svg") {
+        let stitching = if svg_options.stitching { Some(stitcher::stitch_default(&ts)) } else { None };
+        renderer::svg::write_svg(output, &ts, stitching.as_ref(), &svg_options)
+    } else if output.ends_with(".png") {
+        renderer::raster::write_png(output, &ts, size, size)
+    } else {
+        file_io::surface::write_surface(output, timestep, &ts)
+    };
+    match written {
+        Ok(_) => println!("Surface at timestep {} written to {}", timestep, output),
+        Err(e) => exit_with(format!("Couldn't write {}: {}", output, e)),
+    }
+}
+
+fn replay_main(snapshots_file_path: &str, csv_file_path: Option<&str>) {
+    let frames = match file_io::surface::read_surfaces(snapshots_file_path) {
+        Err(e) => exit_with(format!("Couldn't load snapshots \"{}\": {}", snapshots_file_path, e)),
+        Ok(f) => f,
+    };
+    if frames.is_empty() {
+        exit_with(format!("{} has no snapshots", snapshots_file_path));
+    }
+    // Snapshots of output.csv are in output.csv.snapshots.csv
+    let csv_file_path = match csv_file_path {
+        Some(c) => Some(String::from(c)),
+        None => snapshots_file_path.rfind(".snapshots.csv").map(|i| String::from(&snapshots_file_path[..i])),
+    };
+    let recorded = match csv_file_path {
+        Some(c) => match summary::read_recorded_run(&c) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                println!("Not showing recorder values: couldn't read {}: {}", c, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let (mut renderer, mut window) = renderer::setup_renderer();
+    renderer::replay::replay_rendering(&mut window, &mut renderer, renderer::replay::Replay::new(frames, recorded))
 }
 
 fn playin_main() {
@@ -84,20 +320,39 @@ fn playin_main() {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let empty_vec: Vec<i64> = Vec::new();
-    let s: Vec<i64> = empty_vec.iter().map(|x| *x).collect();
-    if args.len() < 2 {
-        real_main()
-    } else if args[1] == "debug" {
-        playin_main()
-    } else if args[1] == "smart" {
-        println!("Path hehe: djumba");
-    } else if args[1] == "conrod" {
-        shared_shit::conrod_main();
-    } else if args[1] == "my_gui" {
-        my_gui::my_ui_main();
-    } else if args[1] == "no_gui" {
-        no_gui_main(&args[2], args[3].parse::<u64>().unwrap());
+    let matches = cli::app().get_matches();
+    match matches.subcommand() {
+        ("gui", Some(m)) => real_main(params_from_matches(m)),
+        ("my-gui", Some(_)) => my_gui::my_ui_main(),
+        ("draw", Some(_)) => playin_main(),
+        ("replay", Some(m)) => replay_main(m.value_of("snapshots").unwrap(), m.value_of("csv")),
+        ("conrod", Some(_)) => shared_shit::conrod_main(),
+        ("run", Some(m)) => {
+            let params = params_from_matches(m);
+            let steps = unsigned_arg(m, "steps").unwrap();
+            if m.is_present("tempering") {
+                tempering_main(params, steps)
+            } else if let Some(replicas) = unsigned_arg(m, "replicas") {
+                replicas_main(params, steps, replicas as usize)
+            } else {
+                no_gui_main(params, steps)
+            }
+        }
+        ("sweep", Some(m)) => {
+            let overrides: Vec<String> = m.values_of("set").map(|vs| vs.map(String::from).collect()).unwrap_or_default();
+            sweep_main(m.value_of("spec").unwrap(), &overrides, unsigned_arg(m, "steps").unwrap(), m.value_of("output"), unsigned_arg(m, "threads"))
+        }
+        ("resume", Some(m)) => resume_main(m.value_of("checkpoint").unwrap(), unsigned_arg(m, "steps").unwrap()),
+        ("analyze", Some(m)) => analyze_main(m.values_of("csvs").unwrap().collect(), m.value_of("output")),
+        ("export", Some(m)) => {
+            let svg_options = renderer::svg::SvgOptions {
+                convex_hull: m.is_present("convex-hull"),
+                stitching: m.is_present("stitching"),
+                gray_matter: m.is_present("gray-matter"),
+            };
+            let size = unsigned_arg(m, "size").map(|s| s as u32).unwrap_or(renderer::raster::DEFAULT_SIZE);
+            export_main(m.value_of("input").unwrap(), unsigned_arg(m, "timestep"), m.value_of("output").unwrap(), svg_options, size)
+        }
+        _ => real_main(params_or_exit("parameters.toml", &[])),
     }
 }
@@ -2,7 +2,6 @@ use conrod_core::*;
 
 use conrod_core::widget::text_box::Event;
 use conrod_core::widget::Id;
-use file_io::toml_table_to_params;
 use graph::closest_node_to_some_point;
 use graph::types::{INNER, OUTER};
 use linalg_helpers::{dist, lines_intersection, points_to_cyclic_lines, closest_point};
@@ -2,12 +2,14 @@ use conrod_core::*;
 
 use conrod_core::widget::text_box::Event;
 use conrod_core::widget::Id;
+use energy::energy_terms;
 use file_io::recorders::{rec_map, record, RecordingState};
-use file_io::toml_table_to_params;
+use file_io::params_from_file;
 use graph::types::{INNER, OUTER};
 use num_traits::NumCast;
 use regex::Regex;
-use simulated_annealing::{step, SimState};
+use optimizer::step;
+use simulated_annealing::SimState;
 use std::collections::HashMap;
 use std::str::FromStr;
 use types::Params;
@@ -20,13 +22,16 @@ pub struct TextBoxStates {
     pub initial_temperature: (String, usize),
     pub compression_factor: (String, usize),
     pub softness_factor: (String, usize),
+    pub am: (String, usize),
+    pub ap: (String, usize),
+    pub dm: (String, usize),
+    pub dp: (String, usize),
     pub how_smooth: (String, usize),
     pub max_merge_steps_away: (String, usize),
     pub node_addition_threshold: (String, usize),
     pub node_deletion_threshold: (String, usize),
     pub low: (String, usize),
     pub high: (String, usize),
-    pub temperature_param: (String, usize),
 }
 
 impl TextBoxStates {
@@ -38,13 +43,16 @@ impl TextBoxStates {
             initial_temperature: (params.initial_temperature.to_string(), 0),
             compression_factor: (params.compression_factor.to_string(), 0),
             softness_factor: (params.softness_factor.to_string(), 0),
+            am: (params.am.to_string(), 0),
+            ap: (params.ap.to_string(), 0),
+            dm: (params.dm.to_string(), 0),
+            dp: (params.dp.to_string(), 0),
             how_smooth: (params.how_smooth.to_string(), 0),
             max_merge_steps_away: (params.max_merge_steps_away.to_string(), 0),
             node_addition_threshold: (params.node_addition_threshold.to_string(), 0),
             node_deletion_threshold: (params.node_deletion_threshold.to_string(), 0),
             low: (params.low_high.0.to_string(), 0),
             high: (params.low_high.1.to_string(), 0),
-            temperature_param: (params.temperature_param.to_string(), 0),
         }
     }
 }
@@ -65,9 +73,9 @@ pub struct RunModeAppState {
 
 impl RunModeAppState {
     pub fn new() -> Self {
-        let params: Params = match std::fs::read_to_string("parameters.toml") {
-            Err(_) => panic!("No parameters.toml file found in directory"),
-            Ok(content) => toml_table_to_params(content.parse::<toml::Value>().unwrap()),
+        let params: Params = match params_from_file("parameters.toml") {
+            Err(e) => panic!("{}", e),
+            Ok(p) => p,
         };
         let mut r = HashMap::new();
         for (rn, fn_) in rec_map() {
@@ -129,13 +137,16 @@ pub fn handle_app_state(app: &mut RunModeAppState) {
     counter_logic(&mut app.text_box_states.initial_temperature.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.compression_factor.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.softness_factor.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
+    counter_logic(&mut app.text_box_states.am.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
+    counter_logic(&mut app.text_box_states.ap.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
+    counter_logic(&mut app.text_box_states.dm.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
+    counter_logic(&mut app.text_box_states.dp.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.how_smooth.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.max_merge_steps_away.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.node_addition_threshold.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.node_deletion_threshold.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.low.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
     counter_logic(&mut app.text_box_states.high.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
-    counter_logic(&mut app.text_box_states.temperature_param.1, NUM_ITERATIONS_TIL_THING_DISAPPEARS);
 }
 
 macro_rules! make_text_boxes {
@@ -176,6 +187,10 @@ widget_ids! {
         initial_gray_matter_area,
         compression_factor,
         softness_factor,
+        am,
+        ap,
+        dm,
+        dp,
         how_smooth,
         max_merge_steps_away,
         node_addition_threshold,
@@ -193,6 +208,10 @@ widget_ids! {
         tbninitial_gray_matter_area,
         tbncompression_factor,
         tbnsoftness_factor,
+        tbnam,
+        tbnap,
+        tbndm,
+        tbndp,
         tbnhow_smooth,
         tbnmax_merge_steps_away,
         tbnnode_addition_threshold,
@@ -215,6 +234,9 @@ widget_ids! {
         red_convex,
         green_convex,
         blue_convex,
+        // Energy terms
+        title_energy_terms,
+        energy_terms_text,
         // Recorders
         title_recorders,
         energy,
@@ -225,6 +247,16 @@ widget_ids! {
         gray_matter_area,
         num_inner_points,
         num_outer_points,
+        gyrification_index,
+        perimeter_ratio,
+        num_sulci,
+        num_gyri,
+        mean_fold_depth,
+        max_fold_depth,
+        mean_thickness,
+        thickness_std,
+        min_thickness,
+        max_thickness,
         // File navigator for deciding output
         file_nav,
         // Scrollbar
@@ -301,6 +333,16 @@ fn make_recorder_widgets(anchor_id: Id, ids: &Ids, app: &mut RunModeAppState, ui
         (String::from("gray matter area"), ids.gray_matter_area),
         (String::from("num inner points"), ids.num_inner_points),
         (String::from("num outer points"), ids.num_outer_points),
+        (String::from("gyrification index"), ids.gyrification_index),
+        (String::from("perimeter ratio"), ids.perimeter_ratio),
+        (String::from("num sulci"), ids.num_sulci),
+        (String::from("num gyri"), ids.num_gyri),
+        (String::from("mean fold depth"), ids.mean_fold_depth),
+        (String::from("max fold depth"), ids.max_fold_depth),
+        (String::from("mean thickness"), ids.mean_thickness),
+        (String::from("thickness std"), ids.thickness_std),
+        (String::from("min thickness"), ids.min_thickness),
+        (String::from("max thickness"), ids.max_thickness),
     ]
     .into_iter()
     .collect();
@@ -456,6 +498,10 @@ pub fn gui(ui: &mut conrod_core::UiCell, ids: &Ids, app: &mut RunModeAppState) {
         (initial_temperature, tbninitial_temperature, "initial temperature", app, ids, ui, anchor),
         (compression_factor, tbncompression_factor, "compression factor", app, ids, ui, anchor),
         (softness_factor, tbnsoftness_factor, "softness factor", app, ids, ui, anchor),
+        (am, tbnam, "am (white matter coefficient)", app, ids, ui, anchor),
+        (ap, tbnap, "ap (white matter exponent)", app, ids, ui, anchor),
+        (dm, tbndm, "dm (gray matter stretch coefficient)", app, ids, ui, anchor),
+        (dp, tbndp, "dp (gray matter stretch exponent)", app, ids, ui, anchor),
         (how_smooth, tbnhow_smooth, "how smooth", app, ids, ui, anchor),
         (
             max_merge_steps_away,
@@ -538,6 +584,16 @@ pub fn gui(ui: &mut conrod_core::UiCell, ids: &Ids, app: &mut RunModeAppState) {
         .set(ids.title_color_sliders, ui);
     let shau = make_color_sliders(ids.title_color_sliders, ids, app, ui);
 
+    widget::Text::new("Energy terms").down_from(shau, 20.0).set(ids.title_energy_terms, ui);
+    let terms: Vec<String> = energy_terms(&app.sim.ts, &app.params)
+        .iter()
+        .map(|(n, v)| format!("{}: {:.6}", n, v))
+        .collect();
+    widget::Text::new(&terms.join("\n"))
+        .down_from(ids.title_energy_terms, 10.0)
+        .font_size(13)
+        .set(ids.energy_terms_text, ui);
+
     /////////////////////////////////
     //// Actual point rendering /////
     /////////////////////////////////
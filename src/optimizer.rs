@@ -0,0 +1,94 @@
+use simulated_annealing;
+use simulated_annealing::SimState;
+use simulated_annealing_dumber_and_better;
+use types::Params;
+
+// -----------------------------------
+// Every front end steps the simulation through this trait, with the engine picked by the `engine` key of the
+// parameters file:
+// "in_place": applies a move to the surface itself and reverts it if it's rejected (simulated_annealing)
+// "cloning": builds the neighbor on a copy of the surface and keeps it if it's accepted (simulated_annealing_dumber_and_better)
+// -----------------------------------
+pub trait Optimizer {
+    fn step(&self, sim_state: &mut SimState, params: &Params);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Engine {
+    InPlace,
+    Cloning,
+}
+
+impl Engine {
+    pub fn from_name(n: &str) -> Option<Engine> {
+        match n {
+            "in_place" => Some(Engine::InPlace),
+            "cloning" => Some(Engine::Cloning),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::InPlace => "in_place",
+            Engine::Cloning => "cloning",
+        }
+    }
+}
+
+pub struct InPlace;
+
+impl Optimizer for InPlace {
+    fn step(&self, sim_state: &mut SimState, params: &Params) {
+        simulated_annealing::step(sim_state, params);
+    }
+}
+
+pub struct Cloning;
+
+impl Optimizer for Cloning {
+    fn step(&self, sim_state: &mut SimState, params: &Params) {
+        simulated_annealing_dumber_and_better::step(sim_state, params);
+    }
+}
+
+pub fn optimizer_choice(engine: Engine) -> &'static dyn Optimizer {
+    match engine {
+        Engine::InPlace => &InPlace,
+        Engine::Cloning => &Cloning,
+    }
+}
+
+pub fn step(sim_state: &mut SimState, params: &Params) {
+    optimizer_choice(params.engine).step(sim_state, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::default_params;
+
+    #[test]
+    fn engines_agree_given_the_same_seed_and_proposals() {
+        let mut p = default_params();
+        p.initial_num_points = 60;
+        p.seed = 7;
+        // The engines add and merge nodes at different points of a step (after vs. before the acceptance test),
+        // so with those switched off every proposal, energy and coin flip has to line up exactly
+        p.node_addition_threshold = std::f64::INFINITY;
+        p.node_deletion_threshold = 0.0;
+
+        let mut in_place = SimState::initial_state(&p);
+        let mut cloning = SimState::initial_state(&p);
+        for _ in 0..200 {
+            InPlace.step(&mut in_place, &p);
+            Cloning.step(&mut cloning, &p);
+        }
+
+        assert_eq!(in_place.timestep, cloning.timestep);
+        assert_eq!(in_place.temperature, cloning.temperature);
+        assert_eq!(in_place.acceptance_rate, cloning.acceptance_rate);
+        assert_eq!(in_place.ts.layers[0].nodes, cloning.ts.layers[0].nodes);
+        assert_eq!(in_place.ts.layers[1].nodes, cloning.ts.layers[1].nodes);
+    }
+}
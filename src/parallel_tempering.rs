@@ -0,0 +1,151 @@
+use energy::energy;
+use rand::{Rng, SeedableRng};
+use simulated_annealing::SimState;
+use optimizer::step;
+use types::{nth_seed, Params, SimRng};
+
+// -----------------------------------
+// Parallel tempering (replica exchange): N replicas of the simulation run side by side, each one at a fixed rung of a
+// geometric temperature ladder going from initial_temperature up to max_temperature. Every swap_interval steps
+// neighbouring replicas try to trade places, so a configuration found while hot can keep cooling down.
+// -----------------------------------
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TemperingParams {
+    pub replicas: usize,
+    pub max_temperature: f64,
+    pub swap_interval: u64,
+}
+
+pub struct ReplicaExchange {
+    pub replicas: Vec<SimState>, // <- replicas[i] always runs at temperatures[i]
+    pub temperatures: Vec<f64>,
+    pub swap_attempts: Vec<u64>, // <- one entry per pair of neighbouring rungs (i, i + 1)
+    pub swap_accepts: Vec<u64>,
+    pub timestep: u64,
+    rng: SimRng,
+}
+
+pub fn temperature_ladder(lowest: f64, highest: f64, rungs: usize) -> Vec<f64> {
+    if rungs < 2 {
+        return vec![lowest];
+    }
+    (0..rungs)
+        .map(|i| lowest * (highest / lowest).powf(i as f64 / (rungs - 1) as f64))
+        .collect()
+}
+
+// Metropolis criterion for trading the states at temperatures t_i and t_j
+pub fn swap_probability(energy_i: f64, temperature_i: f64, energy_j: f64, temperature_j: f64) -> f64 {
+    let exponent = (energy_i - energy_j) * (1.0 / temperature_i - 1.0 / temperature_j);
+    if exponent >= 0.0 {
+        1.0
+    } else {
+        exponent.exp()
+    }
+}
+
+impl ReplicaExchange {
+    pub fn initial_state(p: &Params, tp: &TemperingParams) -> ReplicaExchange {
+        let temperatures = temperature_ladder(p.initial_temperature, tp.max_temperature, tp.replicas);
+        let replicas = temperatures
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                // All from the one shape p's gray matter area is of, since replicas trade surfaces but share p; only
+                // their moves get seeds of their own
+                let mut s = SimState::initial_state(p);
+                s.rng = SimRng::seed_from_u64(nth_seed(p.seed, i as u64 + 1));
+                s.temperature = *t;
+                s
+            })
+            .collect();
+        let pairs = if tp.replicas > 1 { tp.replicas - 1 } else { 0 };
+        ReplicaExchange {
+            replicas,
+            temperatures,
+            swap_attempts: vec![0; pairs],
+            swap_accepts: vec![0; pairs],
+            timestep: 0,
+            rng: SimRng::seed_from_u64(p.seed),
+        }
+    }
+
+    pub fn swap_acceptance_rates(&self) -> Vec<f64> {
+        self.swap_attempts
+            .iter()
+            .zip(&self.swap_accepts)
+            .map(|(attempts, accepts)| if *attempts == 0 { 0.0 } else { *accepts as f64 / *attempts as f64 })
+            .collect()
+    }
+
+    fn attempt_swaps(&mut self, p: &Params) {
+        let energies: Vec<f64> = self.replicas.iter().map(|r| energy(&r.ts, p)).collect();
+        // Alternate between even and odd pairs so every pair gets its turn without a replica moving twice in a round
+        let first = ((self.timestep / p.parallel_tempering.as_ref().unwrap().swap_interval) % 2) as usize;
+        let mut i = first;
+        while i + 1 < self.replicas.len() {
+            self.swap_attempts[i] += 1;
+            let coin_flip = self.rng.gen_range(0.0, 1.0);
+            if swap_probability(energies[i], self.temperatures[i], energies[i + 1], self.temperatures[i + 1]) > coin_flip {
+                self.replicas.swap(i, i + 1);
+                self.replicas[i].temperature = self.temperatures[i];
+                self.replicas[i + 1].temperature = self.temperatures[i + 1];
+                self.swap_accepts[i] += 1;
+            }
+            i += 2;
+        }
+    }
+
+    pub fn step(&mut self, p: &Params) {
+        for (replica, t) in self.replicas.iter_mut().zip(&self.temperatures) {
+            step(replica, p);
+            // The ladder, not the cooling schedule, decides the temperature of each replica
+            replica.temperature = *t;
+        }
+        self.timestep += 1;
+        let swap_interval = p.parallel_tempering.as_ref().unwrap().swap_interval;
+        if swap_interval > 0 && self.timestep % swap_interval == 0 {
+            self.attempt_swaps(p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::default_params;
+
+    #[test]
+    fn ladder_goes_from_lowest_to_highest() {
+        let ladder = temperature_ladder(1.0, 16.0, 5);
+        assert_eq!(ladder.len(), 5);
+        assert!((ladder[0] - 1.0).abs() < 1e-12);
+        assert!((ladder[2] - 4.0).abs() < 1e-12);
+        assert!((ladder[4] - 16.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn colder_replica_always_takes_the_lower_energy() {
+        assert_eq!(swap_probability(5.0, 1.0, 3.0, 2.0), 1.0);
+        assert!(swap_probability(3.0, 1.0, 5.0, 2.0) < 1.0);
+    }
+
+    #[test]
+    fn replicas_stay_on_their_rung() {
+        let mut p = default_params();
+        p.initial_num_points = 40;
+        p.parallel_tempering = Some(TemperingParams {
+            replicas: 3,
+            max_temperature: 100.0,
+            swap_interval: 2,
+        });
+        let mut re = ReplicaExchange::initial_state(&p, p.parallel_tempering.as_ref().unwrap());
+        for _ in 0..20 {
+            re.step(&p);
+        }
+        for (r, t) in re.replicas.iter().zip(&re.temperatures) {
+            assert_eq!(r.temperature, *t);
+        }
+        assert_eq!(re.swap_attempts.iter().sum::<u64>(), 10);
+    }
+}
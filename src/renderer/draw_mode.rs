@@ -13,7 +13,8 @@ use {file_io, stitcher};
 
 use simulated_annealing;
 use stitcher::types::{Stitching, Strategy};
-use types::Params;
+use rand::SeedableRng;
+use types::{Params, SimRng};
 
 fn mk_lines(points: &Vec<(f64, f64)>, color: Color) -> Vec<Line> {
     let mut lines = Vec::new();
@@ -35,7 +36,7 @@ struct StateBag {
     pub stitch_strat: Strategy,
     pub initial_gm: f64,
     pub temp: f64,
-    pub rng: rand::rngs::ThreadRng,
+    pub rng: SimRng,
     pub params: Params,
 }
 
@@ -50,7 +51,7 @@ impl StateBag {
             stitch_strat: stitch_strat,
             initial_gm: 0.0,
             temp: 0.0,
-            rng: Default::default(),
+            rng: SimRng::seed_from_u64(params.seed),
             params,
         }
     }
@@ -302,9 +303,9 @@ fn state_effects(s: &State, e: Event, last_mouse_pos: (f64, f64)) -> State {
                 State::SurfacePushing(ts.clone(), stitcher::stitch_choice(ts, new_stitch_choice), new_stitch_choice)
             }
             Some(piston::Button::Keyboard(piston::Key::S)) => {
-                let params: Params = match std::fs::read_to_string("parameters.toml") {
-                    Err(_) => panic!("No parameters.toml file found in directory"),
-                    Ok(content) => file_io::toml_table_to_params(content.parse::<toml::Value>().unwrap()),
+                let params: Params = match file_io::params_from_file("parameters.toml") {
+                    Err(e) => panic!("{}", e),
+                    Ok(p) => p,
                 };
                 State::SurfaceOptimizing(StateBag {
                     ts: ts.clone(),
@@ -313,7 +314,7 @@ fn state_effects(s: &State, e: Event, last_mouse_pos: (f64, f64)) -> State {
                     stitch_strat: Strategy::Greedy,
                     initial_gm: 0.0,
                     temp: 0.0,
-                    rng: rand::thread_rng(),
+                    rng: SimRng::seed_from_u64(params.seed),
                     params: params,
                 })
             }
@@ -1,6 +1,9 @@
 mod consts;
 pub mod draw_mode;
 mod junk;
+pub mod raster;
+pub mod replay;
+pub mod svg;
 mod types;
 
 use glutin_window::GlutinWindow as Window;
@@ -13,7 +16,7 @@ use graph;
 use file_io::recorders;
 use piston::{Button, Event, PressEvent};
 use simulated_annealing;
-use simulated_annealing_dumber_and_better;
+use optimizer;
 
 use graph::types::{NodeChange, NodeChangeMap, Smooth, ThickSurface, INNER, OUTER};
 use renderer::types::Line;
@@ -210,7 +213,7 @@ pub fn setup_optimization_and_loop<F>(
 
         render_state = next_state(e.press_args(), render_state);
         match render_state.step_type {
-            StepType::Automatic => simulated_annealing_dumber_and_better::step(sim_state, params), // simulated_annealing::step(sim_state, params),
+            StepType::Automatic => optimizer::step(sim_state, params),
             StepType::Reset => *sim_state = simulated_annealing::SimState::initial_state(params),
             _ => {}
         }
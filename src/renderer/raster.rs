@@ -0,0 +1,260 @@
+use graph::types::ThickSurface;
+use image::{Rgba, RgbaImage};
+use renderer::lines_from_thick_surface;
+use renderer::types::{Color, Line};
+use std::path::Path;
+
+// -----------------------------------
+// Draws the same lines Renderer::render does, on the CPU and into an image instead of a window, so headless runs
+// can leave a frame sequence (and, stitched together, an animated GIF) behind. Coordinates map to pixels the way
+// they map to the window: [-1, 1] on both axes fills the image, y up, on a black background.
+// -----------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameParams {
+    pub interval: u64,
+    pub width: u32,
+    pub height: u32,
+    pub gif: bool,
+    pub gif_delay: u16, // <- how long each frame of the GIF shows, in hundredths of a second
+}
+
+pub const DEFAULT_SIZE: u32 = 800;
+pub const DEFAULT_GIF_DELAY: u16 = 10;
+
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+// output.csv -> output.csv.frame00001000.png, padded so the frames sort by timestep
+pub fn frame_file_path(output_file_path: &str, timestep: u64) -> String {
+    format!("{}.frame{:08}.png", output_file_path, timestep)
+}
+
+// output.csv -> output.csv.gif
+pub fn gif_file_path(output_file_path: &str) -> String {
+    format!("{}.gif", output_file_path)
+}
+
+fn image_error(e: image::ImageError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+// Paints `color` over the pixel, `coverage` being how much of the pixel the line covers
+fn blend(img: &mut RgbaImage, x: i64, y: i64, color: Color, coverage: f64) {
+    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+        return;
+    }
+    let alpha = (coverage * color[3] as f64).max(0.0).min(1.0);
+    let pixel = img.get_pixel_mut(x as u32, y as u32);
+    for c in 0..3 {
+        pixel[c] = (pixel[c] as f64 * (1.0 - alpha) + color[c] as f64 * 255.0 * alpha).round() as u8;
+    }
+}
+
+// Xiaolin Wu's antialiased line, in pixel coordinates
+fn draw_line(img: &mut RgbaImage, (x0, y0): (f64, f64), (x1, y1): (f64, f64), color: Color) {
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    // Walk along the longer axis, calling it x; pixel centres are at .5
+    let (mut a0, mut b0, mut a1, mut b1) = if steep {
+        (y0 - 0.5, x0 - 0.5, y1 - 0.5, x1 - 0.5)
+    } else {
+        (x0 - 0.5, y0 - 0.5, x1 - 0.5, y1 - 0.5)
+    };
+    if a0 > a1 {
+        std::mem::swap(&mut a0, &mut a1);
+        std::mem::swap(&mut b0, &mut b1);
+    }
+    let gradient = if a1 - a0 == 0.0 { 0.0 } else { (b1 - b0) / (a1 - a0) };
+    let along = if steep { img.height() } else { img.width() } as f64;
+    let (first, last) = (a0.round().max(0.0), a1.round().min(along - 1.0));
+    let mut a = first;
+    while a <= last {
+        let b = b0 + gradient * (a - a0);
+        let (b_floor, fraction) = (b.floor(), b - b.floor());
+        for (b, coverage) in &[(b_floor, 1.0 - fraction), (b_floor + 1.0, fraction)] {
+            if steep {
+                blend(img, *b as i64, a as i64, color, *coverage);
+            } else {
+                blend(img, a as i64, *b as i64, color, *coverage);
+            }
+        }
+        a += 1.0;
+    }
+}
+
+pub fn rasterize(lines: &[Line], width: u32, height: u32) -> RgbaImage {
+    let mut img = RgbaImage::from_pixel(width, height, BACKGROUND);
+    let (w, h) = (width as f64, height as f64);
+    let to_pixels = |x: f64, y: f64| ((x + 1.0) * w / 2.0, (1.0 - y) * h / 2.0);
+    for l in lines {
+        let (x1, y1, x2, y2) = l.points;
+        draw_line(&mut img, to_pixels(x1, y1), to_pixels(x2, y2), l.color);
+    }
+    img
+}
+
+pub fn frame_of(ts: &ThickSurface, width: u32, height: u32) -> RgbaImage {
+    rasterize(&lines_from_thick_surface(ts), width, height)
+}
+
+pub fn write_png(path: &str, ts: &ThickSurface, width: u32, height: u32) -> std::io::Result<()> {
+    frame_of(ts, width, height).save(path).map_err(image_error)
+}
+
+// Frames that don't all have the first one's size are an error
+pub fn write_gif<I: IntoIterator<Item = std::io::Result<RgbaImage>>>(path: &str, frames: I, delay: u16) -> std::io::Result<()> {
+    let mut encoder: Option<gif::Encoder<std::fs::File>> = None;
+    let mut size = (0, 0);
+    for frame in frames {
+        let frame = frame?;
+        if encoder.is_none() {
+            size = (frame.width() as u16, frame.height() as u16);
+            let mut e = gif::Encoder::new(std::fs::File::create(path)?, size.0, size.1, &[])?;
+            gif::SetParameter::set(&mut e, gif::Repeat::Infinite)?;
+            encoder = Some(e);
+        }
+        if (frame.width() as u16, frame.height() as u16) != size {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "GIF frames of different sizes"));
+        }
+        let mut pixels = frame.into_raw();
+        let mut gif_frame = gif::Frame::from_rgba_speed(size.0, size.1, &mut pixels, 10);
+        gif_frame.delay = delay;
+        encoder.as_mut().unwrap().write_frame(&gif_frame)?;
+    }
+    match encoder {
+        Some(_) => Ok(()),
+        None => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "no frames to make a GIF of")),
+    }
+}
+
+// -----------------------------------
+// Writes a frame every `interval` timesteps of a run and, through `finish`, one more at the end, after which the
+// frames get put together into <output_file_path>.gif if the parameters ask for it.
+// -----------------------------------
+pub struct Frames {
+    output_file_path: String,
+    params: FrameParams,
+    written: Vec<u64>,
+}
+
+impl Frames {
+    pub fn create(output_file_path: &str, params: FrameParams) -> Frames {
+        Frames {
+            output_file_path: String::from(output_file_path),
+            params,
+            written: Vec::new(),
+        }
+    }
+
+    // For a run resumed at `timestep`: the frames from before it are already on disk and go into the GIF too
+    pub fn resume(output_file_path: &str, params: FrameParams, timestep: u64) -> Frames {
+        let written = match params.interval {
+            0 => Vec::new(),
+            interval => (0..timestep)
+                .step_by(interval as usize)
+                .filter(|t| Path::new(&frame_file_path(output_file_path, *t)).exists())
+                .collect(),
+        };
+        Frames {
+            output_file_path: String::from(output_file_path),
+            params,
+            written,
+        }
+    }
+
+    fn write(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        write_png(
+            &frame_file_path(&self.output_file_path, timestep),
+            ts,
+            self.params.width,
+            self.params.height,
+        )?;
+        self.written.push(timestep);
+        Ok(())
+    }
+
+    pub fn record(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        match self.params.interval {
+            interval if interval > 0 && timestep % interval == 0 => self.write(timestep, ts),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn finish(&mut self, timestep: u64, ts: &ThickSurface) -> std::io::Result<()> {
+        if self.written.last() != Some(&timestep) {
+            self.write(timestep, ts)?;
+        }
+        if !self.params.gif {
+            return Ok(());
+        }
+        let output_file_path = &self.output_file_path;
+        let frames = self.written.iter().map(|t| {
+            image::open(frame_file_path(output_file_path, *t))
+                .map(|i| i.to_rgba())
+                .map_err(image_error)
+        });
+        write_gif(&gif_file_path(output_file_path), frames, self.params.gif_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::scratch::ScratchDir;
+    use graph::circular_thick_surface;
+    use renderer::consts;
+
+    #[test]
+    fn lines_land_where_the_renderer_would_put_them() {
+        let lines = vec![
+            Line {
+                points: (-1.0, 0.0, 1.0, 0.0),
+                color: consts::GREEN,
+            },
+            Line {
+                points: (0.5, -1.0, 0.5, 1.0),
+                color: consts::RED,
+            },
+        ];
+        let img = rasterize(&lines, 100, 50);
+        // y = 0 is halfway down, between rows 24 and 25, so it's shared between them
+        assert_eq!(img.get_pixel(10, 24)[1], img.get_pixel(10, 25)[1]);
+        assert!(img.get_pixel(10, 25)[1] > 100);
+        // x = 0.5 is three quarters of the way across, between columns 74 and 75
+        assert_eq!(img.get_pixel(74, 5)[0], img.get_pixel(75, 5)[0]);
+        assert!(img.get_pixel(75, 5)[0] > 100);
+        assert_eq!(*img.get_pixel(10, 10), BACKGROUND);
+    }
+
+    #[test]
+    fn a_run_leaves_frames_and_a_gif() {
+        let dir = ScratchDir::new("frames");
+        let output_file_path = dir.path("output.csv");
+        let params = FrameParams {
+            interval: 10,
+            width: 64,
+            height: 48,
+            gif: true,
+            gif_delay: DEFAULT_GIF_DELAY,
+        };
+        let ts = circular_thick_surface(0.5, 0.1, 20);
+        let mut frames = Frames::create(&output_file_path, params);
+        for t in 0..25 {
+            frames.record(t, &ts).unwrap();
+        }
+        frames.finish(25, &ts).unwrap();
+
+        for t in &[0, 10, 20, 25] {
+            let frame = image::open(frame_file_path(&output_file_path, *t)).unwrap().to_rgba();
+            assert_eq!(frame.dimensions(), (64, 48));
+        }
+        let gif = std::fs::File::open(gif_file_path(&output_file_path)).unwrap();
+        let mut reader = gif::Decoder::new(gif).read_info().unwrap();
+        let mut count = 0;
+        while reader.read_next_frame().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 4);
+
+        let resumed = Frames::resume(&output_file_path, params, 20);
+        assert_eq!(resumed.written, vec![0, 10]);
+    }
+}
@@ -0,0 +1,222 @@
+use glutin_window::GlutinWindow as Window;
+use piston::event_loop::{EventSettings, Events};
+use piston::input::{MouseCursorEvent, RenderEvent, UpdateEvent};
+use piston::window::AdvancedWindow;
+use piston::{Button, Key, MouseButton, PressEvent};
+
+use file_io::summary::RecordedRun;
+use graph::types::ThickSurface;
+use renderer::types::{Line, Renderer};
+use renderer::{consts, junk, lines_from_thick_surface};
+
+// -----------------------------------
+// Plays back the snapshots a headless run left behind. Keys:
+//     space            play/pause
+//     left/right       one snapshot back/forward
+//     up/down          twice/half as fast
+//     home/end         first/last snapshot
+//     digits + enter   jump to the last snapshot at or before that timestep
+// Clicking the bar at the bottom jumps there too. The window title shows the timestep and, if there's a recorder
+// CSV, the values recorded at it.
+// -----------------------------------
+const BAR_Y: f64 = -0.97;
+const BAR_X: (f64, f64) = (-0.9, 0.9);
+
+pub struct Replay {
+    frames: Vec<(u64, ThickSurface)>,
+    recorded: Option<RecordedRun>,
+    current: usize,
+    playing: bool,
+    frames_per_second: f64,
+    since_last_frame: f64,
+    typed: String, // <- digits of a timestep to jump to, until enter is pressed
+}
+
+impl Replay {
+    pub fn new(frames: Vec<(u64, ThickSurface)>, recorded: Option<RecordedRun>) -> Replay {
+        Replay {
+            frames,
+            recorded,
+            current: 0,
+            playing: false,
+            frames_per_second: 10.0,
+            since_last_frame: 0.0,
+            typed: String::new(),
+        }
+    }
+
+    pub fn timestep(&self) -> u64 {
+        self.frames[self.current].0
+    }
+
+    fn step_by(&mut self, delta: isize) {
+        let last = self.frames.len() as isize - 1;
+        self.current = (self.current as isize + delta).max(0).min(last) as usize;
+    }
+
+    pub fn jump_to(&mut self, timestep: u64) {
+        self.current = self.frames.iter().rposition(|(t, _)| *t <= timestep).unwrap_or(0);
+    }
+
+    pub fn press(&mut self, key: Key) {
+        match key {
+            Key::Space => self.playing = !self.playing,
+            Key::Right => self.step_by(1),
+            Key::Left => self.step_by(-1),
+            Key::Up => self.frames_per_second = (self.frames_per_second * 2.0).min(240.0),
+            Key::Down => self.frames_per_second = (self.frames_per_second / 2.0).max(0.25),
+            Key::Home => self.current = 0,
+            Key::End => self.current = self.frames.len() - 1,
+            Key::Backspace => {
+                self.typed.pop();
+            }
+            Key::Return => {
+                if let Ok(t) = self.typed.parse::<u64>() {
+                    self.jump_to(t);
+                }
+                self.typed.clear();
+            }
+            _ => {
+                let digit = (key as u32).wrapping_sub(Key::D0 as u32);
+                if digit < 10 {
+                    self.typed.push_str(&digit.to_string());
+                }
+            }
+        }
+    }
+
+    // Moves forward as many snapshots as `dt` seconds are worth at the current speed, stopping at the last one
+    pub fn advance(&mut self, dt: f64) {
+        if !self.playing {
+            return;
+        }
+        self.since_last_frame += dt;
+        let frame_time = 1.0 / self.frames_per_second;
+        while self.since_last_frame >= frame_time {
+            self.since_last_frame -= frame_time;
+            self.step_by(1);
+        }
+        if self.current == self.frames.len() - 1 {
+            self.playing = false;
+        }
+    }
+
+    // `x` in [-1, 1]; only clicks on the bar count
+    fn click(&mut self, (x, y): (f64, f64)) {
+        if (y - BAR_Y).abs() < 0.03 && x >= BAR_X.0 && x <= BAR_X.1 {
+            let fraction = (x - BAR_X.0) / (BAR_X.1 - BAR_X.0);
+            self.current = (fraction * (self.frames.len() - 1) as f64).round() as usize;
+        }
+    }
+
+    pub fn title(&self) -> String {
+        let mut ret = format!(
+            "timestep {} ({}/{}) {}, {} snapshots/s",
+            self.timestep(),
+            self.current + 1,
+            self.frames.len(),
+            if self.playing { "playing" } else { "paused" },
+            self.frames_per_second
+        );
+        if let Some(r) = &self.recorded {
+            // The last snapshot is usually one step past the last recorded row
+            if let Some((_, vals)) = r.rows.range(..=self.timestep()).next_back() {
+                for (c, v) in r.columns.iter().zip(vals) {
+                    ret.push_str(&format!(" | {}: {:.5}", c, v));
+                }
+            }
+        }
+        if !self.typed.is_empty() {
+            ret.push_str(&format!(" | go to timestep: {}", self.typed));
+        }
+        ret
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let mut lines = lines_from_thick_surface(&self.frames[self.current].1);
+        let progress = if self.frames.len() > 1 {
+            self.current as f64 / (self.frames.len() - 1) as f64
+        } else {
+            1.0
+        };
+        lines.push(Line {
+            points: (BAR_X.0, BAR_Y, BAR_X.1, BAR_Y),
+            color: consts::TURQUOISE,
+        });
+        lines.push(Line {
+            points: (BAR_X.0, BAR_Y, BAR_X.0 + progress * (BAR_X.1 - BAR_X.0), BAR_Y),
+            color: consts::GREEN,
+        });
+        lines
+    }
+}
+
+pub fn replay_rendering(window: &mut Window, renderer: &mut Renderer, mut replay: Replay) {
+    let mut events = Events::new(EventSettings::new());
+    let mut last_mouse_pos = (0.0, 0.0);
+    let mut title = String::new();
+    while let Some(e) = events.next(window) {
+        if let Some(args) = e.render_args() {
+            renderer.render(&args, &replay.lines());
+        }
+        if let Some(args) = e.update_args() {
+            replay.advance(args.dt);
+        }
+        if let Some([x, y]) = e.mouse_cursor_args() {
+            last_mouse_pos = junk::from_window_to_minus1_1(x, y, consts::WINDOW_SIZE.0, consts::WINDOW_SIZE.1);
+        }
+        match e.press_args() {
+            Some(Button::Keyboard(k)) => replay.press(k),
+            Some(Button::Mouse(MouseButton::Left)) => replay.click(last_mouse_pos),
+            _ => {}
+        }
+        let new_title = replay.title();
+        if new_title != title {
+            window.set_title(new_title.clone());
+            title = new_title;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::circular_thick_surface;
+
+    fn replay() -> Replay {
+        let frames = (0..5).map(|i| (i * 10, circular_thick_surface(1.0, 0.1, 10))).collect();
+        let recorded = RecordedRun {
+            columns: vec![String::from("energy")],
+            rows: (0..41).map(|t| (t, vec![t as f64])).collect(),
+        };
+        Replay::new(frames, Some(recorded))
+    }
+
+    #[test]
+    fn stepping_and_jumping_stay_in_bounds() {
+        let mut r = replay();
+        r.press(Key::Left);
+        assert_eq!(r.timestep(), 0);
+        r.press(Key::End);
+        r.press(Key::Right);
+        assert_eq!(r.timestep(), 40);
+        for k in &[Key::D2, Key::D5, Key::Return] {
+            r.press(*k);
+        }
+        assert_eq!(r.timestep(), 20);
+        r.jump_to(1000);
+        assert_eq!(r.timestep(), 40);
+    }
+
+    #[test]
+    fn playing_advances_with_time_and_stops_at_the_end() {
+        let mut r = replay();
+        r.press(Key::Space);
+        r.advance(0.25); // <- 10 snapshots per second
+        assert_eq!(r.timestep(), 20);
+        assert!(r.title().contains("energy: 20.0"));
+        r.advance(10.0);
+        assert_eq!(r.timestep(), 40);
+        assert!(r.title().contains("paused"));
+    }
+}
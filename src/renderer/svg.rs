@@ -0,0 +1,159 @@
+use graph::convex_hull::convex_hull_from_graph;
+use graph::types::{Graph, ThickSurface, INNER, OUTER};
+use renderer::consts;
+use renderer::types::Color;
+use std::fmt::Write as FmtWrite;
+use stitcher::types::Stitching;
+
+// -----------------------------------
+// Draws a ThickSurface to an SVG file, no window needed. Same colours as the renderer: the outer surface in pink,
+// the inner one in blue, plus optionally the convex hull of the outer surface, the stitching correspondences between
+// the two and the gray matter in between filled in. The y axis points up, like in the renderer.
+// -----------------------------------
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SvgOptions {
+    pub convex_hull: bool,
+    pub stitching: bool,
+    pub gray_matter: bool,
+}
+
+// SVGs of a run at the given timesteps, from the `svg` section of the parameters file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SvgParams {
+    pub timesteps: Vec<u64>,
+    pub options: SvgOptions,
+}
+
+const SIZE: f64 = 800.0; // <- in pixels, the longest side
+const MARGIN: f64 = 0.05; // <- around the outer surface, as a fraction of its size
+
+// output.csv -> output.csv.t1000.svg
+pub fn svg_file_path(output_file_path: &str, timestep: u64) -> String {
+    format!("{}.t{}.svg", output_file_path, timestep)
+}
+
+fn rgb(c: Color) -> String {
+    format!("rgb({},{},{})", (c[0] * 255.0).round(), (c[1] * 255.0).round(), (c[2] * 255.0).round())
+}
+
+fn ring(g: &Graph) -> Vec<(f64, f64)> {
+    let mut ret = g.to_vec_of_points();
+    ret.pop(); // <- to_vec_of_points closes the ring by repeating the first node
+    ret
+}
+
+fn points_attr(ring: &[(f64, f64)]) -> String {
+    ring.iter().map(|(x, y)| format!("{},{}", x, -y)).collect::<Vec<String>>().join(" ")
+}
+
+fn path_data(ring: &[(f64, f64)]) -> String {
+    format!("M {} Z", points_attr(ring).replace(' ', " L "))
+}
+
+fn polygon(ring: &[(f64, f64)], color: Color) -> String {
+    format!(
+        "<polygon points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1\" vector-effect=\"non-scaling-stroke\"/>\n",
+        points_attr(ring),
+        rgb(color)
+    )
+}
+
+fn line((x1, y1): (f64, f64), (x2, y2): (f64, f64), color: Color) -> String {
+    format!(
+        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"0.5\" vector-effect=\"non-scaling-stroke\"/>\n",
+        x1,
+        -y1,
+        x2,
+        -y2,
+        rgb(color)
+    )
+}
+
+// `stitching` is only used with options.stitching
+pub fn svg_of(ts: &ThickSurface, stitching: Option<&Stitching>, options: &SvgOptions) -> String {
+    let outer = ring(&ts.layers[OUTER]);
+    let inner = ring(&ts.layers[INNER]);
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for (x, y) in outer.iter().chain(inner.iter()) {
+        min_x = min_x.min(*x);
+        max_x = max_x.max(*x);
+        min_y = min_y.min(-y);
+        max_y = max_y.max(-y);
+    }
+    let margin = MARGIN * (max_x - min_x).max(max_y - min_y);
+    let (w, h) = (max_x - min_x + 2.0 * margin, max_y - min_y + 2.0 * margin);
+    let scale = SIZE / w.max(h);
+
+    let mut svg = String::new();
+    svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+        (w * scale).round(),
+        (h * scale).round(),
+        min_x - margin,
+        min_y - margin,
+        w,
+        h
+    )
+    .unwrap();
+    if options.gray_matter {
+        writeln!(
+            svg,
+            "<path d=\"{} {}\" fill=\"{}\" fill-opacity=\"0.4\" fill-rule=\"evenodd\" stroke=\"none\"/>",
+            path_data(&outer),
+            path_data(&inner),
+            rgb(consts::PURPLE)
+        )
+        .unwrap();
+    }
+    if options.convex_hull {
+        svg.push_str(&polygon(&ring(&convex_hull_from_graph(&ts.layers[OUTER])), consts::TURQUOISE));
+    }
+    if let (true, Some(Stitching::Stitch(layers))) = (options.stitching, stitching) {
+        for (outer_id, correspondents) in &layers[OUTER] {
+            for (inner_id, _, _) in correspondents.iter() {
+                // A stitching can be older than the surface, so nodes it mentions may be gone
+                if let (Some(o), Some(i)) = (ts.layers[OUTER].nodes.get(*outer_id), ts.layers[INNER].nodes.get(*inner_id)) {
+                    svg.push_str(&line((o.x, o.y), (i.x, i.y), consts::GREEN));
+                }
+            }
+        }
+    }
+    svg.push_str(&polygon(&outer, consts::PINK));
+    svg.push_str(&polygon(&inner, consts::BLUE));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn write_svg(path: &str, ts: &ThickSurface, stitching: Option<&Stitching>, options: &SvgOptions) -> std::io::Result<()> {
+    std::fs::write(path, svg_of(ts, stitching, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::circular_thick_surface;
+    use stitcher::stitch_default;
+
+    #[test]
+    fn everything_asked_for_is_drawn() {
+        let ts = circular_thick_surface(1.0, 0.1, 12);
+        let stitching = stitch_default(&ts);
+        let bare = svg_of(&ts, Some(&stitching), &SvgOptions::default());
+        assert_eq!(bare.matches("<polygon").count(), 2);
+        assert!(!bare.contains("<path") && !bare.contains("<line"));
+
+        let everything = SvgOptions {
+            convex_hull: true,
+            stitching: true,
+            gray_matter: true,
+        };
+        let full = svg_of(&ts, Some(&stitching), &everything);
+        assert_eq!(full.matches("<polygon").count(), 3);
+        assert_eq!(full.matches("<path").count(), 1);
+        assert!(full.matches("<line").count() >= 12);
+        assert!(full.contains(&rgb(consts::PINK)) && full.contains(&rgb(consts::BLUE)));
+    }
+}
@@ -1,13 +1,14 @@
+use cooling;
+use energy::{energy_of, Measures};
 use graph;
-use graph::circular_thick_surface;
 use graph::effects::{add_node_, apply_changes, changer_of_choice, merge_nodes_, random_change, revert_changes, smooth_change_out};
+use graph::segment_index::SegmentIndex;
 use graph::types::{NodeChangeMap, Smooth, ThickSurface, INNER, OUTER};
-use linalg_helpers::lines_intersection;
-use rand::prelude::ThreadRng;
-use rand::Rng;
+use initial_shape;
+use rand::{Rng, SeedableRng};
 use stitcher::stitch_default;
 use stitcher::types::Stitching;
-use types::Params;
+use types::{Params, SimRng};
 
 const PRACTICALLY_INFINITY: f64 = 100_000_000.0;
 
@@ -19,7 +20,7 @@ fn neighbor_changes(
     compression_factor: f64,
     stitch: &Stitching,
     low_high: (f64, f64),
-    rng: &mut rand::rngs::ThreadRng,
+    rng: &mut SimRng,
 ) -> (NodeChangeMap, NodeChangeMap) {
     let outer_change = random_change(&ts.layers[layer_to_push], low_high, rng);
     let smoothed_changes = smooth_change_out(&ts.layers[layer_to_push], outer_change.clone(), Smooth::Count(how_smooth));
@@ -33,24 +34,6 @@ fn neighbor_changes(
     (smoothed_changes, smoothed_inner_changes)
 }
 
-pub fn energy(ts: &ThickSurface, initial_gray_matter_area: f64) -> f64 {
-    let white_matter = graph::area(&ts.layers[INNER]);
-    let gray_matter = (graph::area(&ts.layers[OUTER]) - white_matter).abs();
-    let gray_matter_stretch = (gray_matter - initial_gray_matter_area).abs();
-
-    // TODO: parametrize?
-    white_matter + (1.0 + gray_matter_stretch).powf(2.0)
-}
-
-pub fn temperature(sim_state: &SimState, slope: f64) -> f64 {
-    let new = sim_state.timestep as f64 * slope;
-    if new < 0.0 {
-        0.0
-    } else {
-        new
-    }
-}
-
 fn probability_to_accept_neighbor_state(energy_state: f64, energy_neighbor: f64, temperature: f64) -> f64 {
     if temperature < 0.0 {
         if energy_neighbor < energy_state {
@@ -65,54 +48,73 @@ fn probability_to_accept_neighbor_state(energy_state: f64, energy_neighbor: f64,
     }
 }
 
+fn update_index(index: &mut SegmentIndex, ts: &ThickSurface, outer_changes: &NodeChangeMap, inner_changes: &NodeChangeMap) {
+    index.update(ts, OUTER, outer_changes.unwrap().keys().cloned());
+    index.update(ts, INNER, inner_changes.unwrap().keys().cloned());
+}
+
+fn crossing_near_changes(index: &SegmentIndex, ts: &ThickSurface, outer_changes: &NodeChangeMap, inner_changes: &NodeChangeMap) -> Option<(f64, f64)> {
+    index
+        .intersection_near(ts, OUTER, outer_changes.unwrap().keys().cloned())
+        .or_else(|| index.intersection_near(ts, INNER, inner_changes.unwrap().keys().cloned()))
+}
+
 fn intersection_effects(
     ts: &mut ThickSurface,
+    index: &mut SegmentIndex,
     outer_changes: &NodeChangeMap,
     inner_changes: &NodeChangeMap,
     energy_state: f64,
     energy_neighbor: f64,
     temperature: f64,
-    rng: &mut rand::rngs::ThreadRng,
-) {
-    let lines1 = graph::graphs_to_lines(&ts.layers);
+    rng: &mut SimRng,
+) -> bool {
     let coin_flip = rng.gen_range(0.0, 1.0);
-    match lines_intersection(&lines1) {
-        Some(_) => {
+    match crossing_near_changes(index, ts, outer_changes, inner_changes) {
+        Some(int1) => {
             revert_changes(&mut ts.layers[OUTER], outer_changes);
             revert_changes(&mut ts.layers[INNER], inner_changes);
+            update_index(index, ts, outer_changes, inner_changes);
 
-            let lines2 = graph::graphs_to_lines(&ts.layers);
-            match lines_intersection(&lines2) {
-                Some(_) => {
-                    println!("Howwwwwwww?? lines1:\n{:?}\nlines2:\n{:?}", lines1, lines2);
+            match crossing_near_changes(index, ts, outer_changes, inner_changes) {
+                Some(int2) => {
+                    println!("Howwwwwwww?? crossing at {:?} before reverting, {:?} after", int1, int2);
                     panic!("caceta")
                 }
                 None => {}
             }
+            false
         }
         None => {
             if probability_to_accept_neighbor_state(energy_state, energy_neighbor, temperature) < coin_flip {
                 revert_changes(&mut ts.layers[OUTER], outer_changes);
                 revert_changes(&mut ts.layers[INNER], inner_changes);
+                update_index(index, ts, outer_changes, inner_changes);
+                false
+            } else {
+                true
             }
         }
     }
 }
 
-fn add_single_node_effects(ts: &mut ThickSurface, layer_to_add: usize, addition_threshold: f64) {
+// Returns whether a node was added
+fn add_single_node_effects(ts: &mut ThickSurface, index: &mut SegmentIndex, layer_to_add: usize, addition_threshold: f64) -> bool {
     let graph_to_which_add = &ts.layers[layer_to_add];
 
     for n in &graph_to_which_add.nodes {
         match graph::node_to_add(graph_to_which_add, n, n.next(&graph_to_which_add), addition_threshold) {
             Some(addition) => {
                 add_node_(ts, layer_to_add, &addition);
+                index.update(ts, layer_to_add, vec![addition.n.id]);
                 // println!("addition: {:?}", addition);
                 // println!("prev: {:?}\nnext: {:?}\n", ts.layers[layer_to_add].nodes[addition.n.prev_id], ts.layers[layer_to_add].nodes[addition.n.next_id]);
-                break; // THE BREAK IS WHAT LETS THIS WORK, GODDAMN
+                return true;
             }
             None => {}
         }
     }
+    false
 }
 
 fn aaa(ts: &ThickSurface) {
@@ -127,50 +129,77 @@ fn aaa(ts: &ThickSurface) {
     // println!("BBB");
 }
 
-fn delete_single_node_effects(ts: &mut ThickSurface, layer_from_which_delete: usize, deletion_threshold: f64, max_merge_steps_away: usize) {
+// Returns whether nodes were merged
+fn delete_single_node_effects(
+    ts: &mut ThickSurface,
+    index: &mut SegmentIndex,
+    layer_from_which_delete: usize,
+    deletion_threshold: f64,
+    max_merge_steps_away: usize,
+) -> bool {
+    let mut merged = false;
     let graph_from_which_delete = &ts.layers[layer_from_which_delete];
     for n in &graph_from_which_delete.nodes {
-        match graph::nodes_to_merge(ts, layer_from_which_delete, n, deletion_threshold, max_merge_steps_away, true) {
+        match graph::nodes_to_merge(ts, layer_from_which_delete, n, deletion_threshold, max_merge_steps_away, Some(index)) {
             Some(deletion) => {
                 merge_nodes_(ts, &deletion);
+                // Merging renumbers nodes, so the index can't be patched up segment by segment
+                index.rebuild(ts);
 
-                let lines = graph::graphs_to_lines(&ts.layers);
-                match lines_intersection(&lines) {
+                match index.any_intersection(ts) {
                     Some(_) => println!("How??"),
                     None => {}
                 }
 
+                merged = true;
                 break; // THE BREAK IS WHAT LETS THIS WORK, GODDAMN
             }
             None => {}
         }
     }
-    aaa(ts)
+    aaa(ts);
+    merged
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SimState {
     pub ts: ThickSurface,
     pub temperature: f64,
     pub stitching: Stitching,
     pub timestep: u64,
-    pub rng: ThreadRng,
+    pub acceptance_rate: f64, // <- moving average of how many proposed moves were accepted
+    pub rng: SimRng,
+    #[serde(skip)] // <- these two are rebuilt from ts whenever a state is loaded, see rebuild_caches
+    pub segment_index: SegmentIndex,
+    #[serde(skip)]
+    pub measures: Measures,
 }
 
 impl SimState {
     pub fn initial_state(p: &Params) -> SimState {
-        let ts = circular_thick_surface(p.initial_radius, p.initial_thickness, p.initial_num_points);
+        // Parameters files have their initial_shape checked when they're loaded
+        let ts = initial_shape::thick_surface(p).unwrap_or_else(|e| panic!("Bad initial_shape: {}", e));
         let s = stitch_default(&ts);
-        let rng = rand::thread_rng();
+        let rng = SimRng::seed_from_u64(p.seed);
+        let segment_index = SegmentIndex::new(&ts);
+        let measures = Measures::of(&ts);
 
         SimState {
             ts: ts,
             temperature: p.initial_temperature,
             stitching: s,
             timestep: 0,
+            acceptance_rate: 1.0,
             rng: rng,
+            segment_index: segment_index,
+            measures: measures,
         }
     }
+
+    pub fn rebuild_caches(&mut self) {
+        self.segment_index = SegmentIndex::new(&self.ts);
+        self.measures = Measures::of(&self.ts);
+    }
 }
 
 pub fn step(sim_state: &mut SimState, params: &Params) -> Vec<NodeChangeMap> {
@@ -191,13 +220,19 @@ pub fn step(sim_state: &mut SimState, params: &Params) -> Vec<NodeChangeMap> {
         &mut sim_state.rng,
     );
 
-    let energy_state = energy(&sim_state.ts, params.initial_gray_matter_area);
+    let measures_neighbor = sim_state
+        .measures
+        .after_changes(&sim_state.ts, OUTER, &outer_changes)
+        .after_changes(&sim_state.ts, INNER, &inner_changes);
+    let energy_state = energy_of(&sim_state.measures, params);
+    let energy_neighbor = energy_of(&measures_neighbor, params);
     apply_changes(&mut sim_state.ts.layers[OUTER], &outer_changes);
     apply_changes(&mut sim_state.ts.layers[INNER], &inner_changes);
-    let energy_neighbor = energy(&sim_state.ts, params.initial_gray_matter_area);
+    update_index(&mut sim_state.segment_index, &sim_state.ts, &outer_changes, &inner_changes);
 
-    intersection_effects(
+    let accepted = intersection_effects(
         &mut sim_state.ts,
+        &mut sim_state.segment_index,
         &outer_changes,
         &inner_changes,
         energy_state,
@@ -205,13 +240,74 @@ pub fn step(sim_state: &mut SimState, params: &Params) -> Vec<NodeChangeMap> {
         sim_state.temperature,
         &mut sim_state.rng,
     );
-    add_single_node_effects(&mut sim_state.ts, OUTER, node_addition_threshold);
-    add_single_node_effects(&mut sim_state.ts, INNER, node_addition_threshold);
+    if accepted {
+        sim_state.measures = measures_neighbor;
+    }
+    let outer_added = add_single_node_effects(&mut sim_state.ts, &mut sim_state.segment_index, OUTER, node_addition_threshold);
+    let inner_added = add_single_node_effects(&mut sim_state.ts, &mut sim_state.segment_index, INNER, node_addition_threshold);
 
-    delete_single_node_effects(&mut sim_state.ts, OUTER, node_deletion_threshold, params.max_merge_steps_away);
-    delete_single_node_effects(&mut sim_state.ts, INNER, node_deletion_threshold, params.max_merge_steps_away);
+    let outer_merged = delete_single_node_effects(
+        &mut sim_state.ts,
+        &mut sim_state.segment_index,
+        OUTER,
+        node_deletion_threshold,
+        params.max_merge_steps_away,
+    );
+    let inner_merged = delete_single_node_effects(
+        &mut sim_state.ts,
+        &mut sim_state.segment_index,
+        INNER,
+        node_deletion_threshold,
+        params.max_merge_steps_away,
+    );
+    // Adding and merging nodes doesn't come with a change map to work out the difference from, so just start over
+    if outer_added || inner_added || outer_merged || inner_merged {
+        sim_state.measures = Measures::of(&sim_state.ts);
+    }
 
-    sim_state.temperature = temperature(sim_state, params.temperature_param);
+    cooling::update_acceptance_rate(sim_state, accepted);
     sim_state.timestep += 1;
+    sim_state.temperature = cooling::temperature(&params.cooling_schedule, params.initial_temperature, sim_state);
     vec![outer_changes, inner_changes]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::default_params;
+    use simulated_annealing_dumber_and_better;
+
+    fn seeded_params(seed: u64) -> Params {
+        let mut p = default_params();
+        p.initial_num_points = 60;
+        p.seed = seed;
+        p
+    }
+
+    fn run(p: &Params, engine: fn(&mut SimState, &Params), steps: usize) -> Vec<Vec<(f64, f64)>> {
+        let mut s = SimState::initial_state(p);
+        for _ in 0..steps {
+            engine(&mut s, p);
+        }
+        s.ts.layers.iter().map(|g| g.to_vec_of_points()).collect()
+    }
+
+    fn in_place(s: &mut SimState, p: &Params) {
+        step(s, p);
+    }
+
+    #[test]
+    fn same_seed_same_trajectory() {
+        let p = seeded_params(42);
+        assert_eq!(run(&p, in_place, 200), run(&p, in_place, 200));
+        assert_eq!(
+            run(&p, simulated_annealing_dumber_and_better::step, 200),
+            run(&p, simulated_annealing_dumber_and_better::step, 200)
+        );
+    }
+
+    #[test]
+    fn different_seed_different_trajectory() {
+        assert_ne!(run(&seeded_params(1), in_place, 50), run(&seeded_params(2), in_place, 50));
+    }
+}
@@ -1,16 +1,27 @@
+use cooling;
+use energy::{energy_of, Measures};
 use graph;
 use graph::effects::{add_node_, apply_changes, changer_of_choice, merge_nodes_, random_change, smooth_change_out};
+use graph::segment_index::SegmentIndex;
 use graph::types::{Smooth, ThickSurface, INNER, OUTER};
-use linalg_helpers::lines_intersection;
 use rand::Rng;
 use simulated_annealing::SimState;
 use stitcher::types::Stitching;
-use types::Params;
+use types::{Params, SimRng};
 
 pub const PRACTICALLY_INFINITY: f64 = 100_000_000.0;
 
+struct Neighbor {
+    ts: ThickSurface,
+    segment_index: SegmentIndex,
+    measures: Measures,
+    crosses: bool,
+}
+
 fn neighbor(
     ts: &ThickSurface,
+    index: &SegmentIndex,
+    measures: &Measures,
     layer_to_push: usize,
     layer_across: usize,
     how_smooth: usize,
@@ -20,9 +31,10 @@ fn neighbor(
     addition_threshold: f64,
     deletion_threshold: f64,
     max_merge_steps_away: usize,
-    rng: &mut rand::rngs::ThreadRng,
-) -> ThickSurface {
+    rng: &mut SimRng,
+) -> Neighbor {
     let mut ret = ts.clone();
+    let mut ret_index = index.clone();
     let outer_change = random_change(&ret.layers[layer_to_push], low_high, rng);
     let smoothed_changes = smooth_change_out(&ret.layers[layer_to_push], outer_change.clone(), Smooth::Count(how_smooth));
     let smoothed_inner_changes = changer_of_choice(
@@ -32,33 +44,41 @@ fn neighbor(
         compression_factor,
         stitch,
     );
+    let mut ret_measures = measures
+        .after_changes(&ret, layer_to_push, &smoothed_changes)
+        .after_changes(&ret, layer_across, &smoothed_inner_changes);
     apply_changes(&mut ret.layers[layer_to_push], &smoothed_changes);
     apply_changes(&mut ret.layers[layer_across], &smoothed_inner_changes);
+    ret_index.update(&ret, layer_to_push, smoothed_changes.unwrap().keys().cloned());
+    ret_index.update(&ret, layer_across, smoothed_inner_changes.unwrap().keys().cloned());
+    let moves_cross = ret_index
+        .intersection_near(&ret, layer_to_push, smoothed_changes.unwrap().keys().cloned())
+        .or_else(|| ret_index.intersection_near(&ret, layer_across, smoothed_inner_changes.unwrap().keys().cloned()))
+        .is_some();
+
+    // Additions split a line in half without moving anything, so they can't make lines cross
+    let pushed_added = add_single_node_effects(&mut ret, &mut ret_index, layer_to_push, addition_threshold);
+    let across_added = add_single_node_effects(&mut ret, &mut ret_index, layer_across, addition_threshold);
+
+    let pushed_merged = delete_single_node_effects(&mut ret, layer_to_push, deletion_threshold, max_merge_steps_away);
+    let across_merged = delete_single_node_effects(&mut ret, layer_across, deletion_threshold, max_merge_steps_away);
+    let merges_cross = if pushed_merged || across_merged {
+        ret_index.rebuild(&ret);
+        ret_index.any_intersection(&ret).is_some()
+    } else {
+        false
+    };
 
-    add_single_node_effects(&mut ret, layer_to_push, addition_threshold);
-    add_single_node_effects(&mut ret, layer_across, addition_threshold);
-
-    delete_single_node_effects(&mut ret, layer_to_push, deletion_threshold, max_merge_steps_away);
-    delete_single_node_effects(&mut ret, layer_across, deletion_threshold, max_merge_steps_away);
-
-    ret
-}
-
-pub fn energy(ts: &ThickSurface, initial_gray_matter_area: f64) -> f64 {
-    let white_matter = graph::area(&ts.layers[INNER]);
-    let gray_matter = (graph::area(&ts.layers[OUTER]) - white_matter).abs();
-    let gray_matter_stretch = (gray_matter - initial_gray_matter_area).abs();
-
-    // TODO: parametrize?
-    white_matter + (1.0 + gray_matter_stretch).powf(2.0)
-}
+    // Adding and merging nodes doesn't come with a change map to work out the difference from, so just start over
+    if pushed_added || across_added || pushed_merged || across_merged {
+        ret_measures = Measures::of(&ret);
+    }
 
-pub fn temperature(sim_state: &SimState, slope: f64) -> f64 {
-    let new = sim_state.timestep as f64 * slope;
-    if new < 0.0 {
-        0.0
-    } else {
-        new
+    Neighbor {
+        ts: ret,
+        segment_index: ret_index,
+        measures: ret_measures,
+        crosses: moves_cross || merges_cross,
     }
 }
 
@@ -76,12 +96,11 @@ fn probability_to_accept_neighbor_state(energy_state: f64, energy_neighbor: f64,
     }
 }
 
-fn should_move_to_neighbor(ts: &ThickSurface, energy_state: f64, energy_neighbor: f64, temperature: f64, rng: &mut rand::rngs::ThreadRng) -> bool {
-    let lines1 = graph::graphs_to_lines(&ts.layers);
+fn should_move_to_neighbor(neighbor_crosses: bool, energy_state: f64, energy_neighbor: f64, temperature: f64, rng: &mut SimRng) -> bool {
     let coin_flip = rng.gen_range(0.0, 1.0);
-    match lines_intersection(&lines1) {
-        Some(_) => false,
-        None => {
+    match neighbor_crosses {
+        true => false,
+        false => {
             if probability_to_accept_neighbor_state(energy_state, energy_neighbor, temperature) < coin_flip {
                 false
             } else {
@@ -91,37 +110,44 @@ fn should_move_to_neighbor(ts: &ThickSurface, energy_state: f64, energy_neighbor
     }
 }
 
-fn add_single_node_effects(ts: &mut ThickSurface, layer_to_add: usize, addition_threshold: f64) {
+// Returns whether a node was added
+fn add_single_node_effects(ts: &mut ThickSurface, index: &mut SegmentIndex, layer_to_add: usize, addition_threshold: f64) -> bool {
     let graph_to_which_add = &ts.layers[layer_to_add];
 
     for n in &graph_to_which_add.nodes {
         match graph::node_to_add(graph_to_which_add, n, n.next(&graph_to_which_add), addition_threshold) {
             Some(addition) => {
                 add_node_(ts, layer_to_add, &addition);
-                break; // THE BREAK IS WHAT LETS THIS WORK, GODDAMN
+                index.update(ts, layer_to_add, vec![addition.n.id]);
+                return true;
             }
             None => {}
         }
     }
+    false
 }
 
-fn delete_single_node_effects(ts: &mut ThickSurface, layer_from_which_delete: usize, deletion_threshold: f64, max_merge_steps_away: usize) {
+// Returns whether a merge happened
+fn delete_single_node_effects(ts: &mut ThickSurface, layer_from_which_delete: usize, deletion_threshold: f64, max_merge_steps_away: usize) -> bool {
     let graph_from_which_delete = &ts.layers[layer_from_which_delete];
     for n in &graph_from_which_delete.nodes {
-        match graph::nodes_to_merge(ts, layer_from_which_delete, n, deletion_threshold, max_merge_steps_away, false) {
+        match graph::nodes_to_merge(ts, layer_from_which_delete, n, deletion_threshold, max_merge_steps_away, None) {
             Some(deletion) => {
                 merge_nodes_(ts, &deletion);
-                break; // THE BREAK IS WHAT LETS THIS WORK, GODDAMN
+                return true;
             }
             None => {}
         }
     }
+    false
 }
 
 pub fn step(sim_state: &mut SimState, params: &Params) {
-    let energy_state = energy(&sim_state.ts, params.initial_gray_matter_area);
+    let energy_state = energy_of(&sim_state.measures, params);
     let neighbor = neighbor(
         &sim_state.ts,
+        &sim_state.segment_index,
+        &sim_state.measures,
         OUTER,
         INNER,
         params.how_smooth,
@@ -133,12 +159,16 @@ pub fn step(sim_state: &mut SimState, params: &Params) {
         params.max_merge_steps_away,
         &mut sim_state.rng,
     );
-    let energy_neighbor = energy(&neighbor, params.initial_gray_matter_area);
+    let energy_neighbor = energy_of(&neighbor.measures, params);
 
-    if should_move_to_neighbor(&neighbor, energy_state, energy_neighbor, sim_state.temperature, &mut sim_state.rng) {
-        sim_state.ts = neighbor;
+    let accepted = should_move_to_neighbor(neighbor.crosses, energy_state, energy_neighbor, sim_state.temperature, &mut sim_state.rng);
+    if accepted {
+        sim_state.ts = neighbor.ts;
+        sim_state.segment_index = neighbor.segment_index;
+        sim_state.measures = neighbor.measures;
     };
 
-    sim_state.temperature = temperature(sim_state, params.temperature_param);
+    cooling::update_acceptance_rate(sim_state, accepted);
     sim_state.timestep += 1;
+    sim_state.temperature = cooling::temperature(&params.cooling_schedule, params.initial_temperature, sim_state);
 }
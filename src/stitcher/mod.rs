@@ -15,6 +15,9 @@ pub fn stitch_choice(ts: &ThickSurface, strategy: types::Strategy) -> types::Sti
     }
 }
 
+// What the simulations stitch with
+pub const DEFAULT_STRATEGY: types::Strategy = types::Strategy::Dijkstra;
+
 pub fn stitch_default(ts: &ThickSurface) -> types::Stitching {
-    stitch_choice(ts, types::Strategy::Dijkstra)
+    stitch_choice(ts, DEFAULT_STRATEGY)
 }
@@ -4,7 +4,7 @@ use graph::types::{Node, ThickSurface, INNER, OUTER};
 use std::collections::HashMap;
 use vec1::Vec1;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ListMap {
     LMap(HashMap<usize, Vec1<(usize, f64, f64)>>),
 }
@@ -68,7 +68,7 @@ impl<'a> IntoIterator for &'a ListMap {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Stitching {
     Stitch(Vec<ListMap>),
 }
@@ -145,4 +145,10 @@ impl Strategy {
             Strategy::Dijkstra => Strategy::Greedy,
         }
     }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Greedy => "greedy",
+            Strategy::Dijkstra => "dijkstra",
+        }
+    }
 }
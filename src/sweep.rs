@@ -0,0 +1,255 @@
+use file_io;
+use headless;
+use file_io::recorders;
+use file_io::{params_from_toml, ParamsError};
+use simulated_annealing::SimState;
+use std::fs::File;
+use std::io::Write;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use types::Params;
+
+// -----------------------------------
+// A sweep is a base parameters file plus the parameters to change from run to run:
+//     base = "parameters.toml"
+//     output_directory = "sweep_output"    # optional, defaults to "sweep_output"
+//     threads = 4                          # optional, defaults to however many cores there are
+//     [grid]                               # every combination of these...
+//     how_smooth = [4, 8, 12]
+//     compression_factor = [1.05, 1.1]
+//     [[runs]]                             # ...plus each of these as is
+//     how_smooth = 20
+// Each run writes its own CSV to output_directory, and manifest.csv there says which run got which parameters.
+// -----------------------------------
+pub type Overrides = Vec<(String, toml::Value)>;
+
+pub struct Sweep {
+    pub output_directory: String,
+    pub threads: usize,
+    pub override_keys: Vec<String>, // <- every key some run overrides, in the order they first show up
+    pub runs: Vec<(Overrides, Params)>,
+}
+
+fn wrong_type(file: &str, key: &str, expected: &'static str, found: &toml::Value) -> ParamsError {
+    ParamsError::WrongType {
+        file: String::from(file),
+        key: String::from(key),
+        expected,
+        found: found.clone(),
+    }
+}
+
+fn grid_combinations(grid: &toml::value::Table, file: &str) -> Result<Vec<Overrides>, ParamsError> {
+    let mut ret: Vec<Overrides> = vec![Vec::new()];
+    for (k, vs) in grid {
+        let vs = vs.as_array().ok_or_else(|| wrong_type(file, &format!("grid.{}", k), "a list of values to try", vs))?;
+        let mut extended = Vec::new();
+        for combination in &ret {
+            for v in vs {
+                let mut c = combination.clone();
+                c.push((k.clone(), v.clone()));
+                extended.push(c);
+            }
+        }
+        ret = extended;
+    }
+    Ok(ret)
+}
+
+fn run_overrides(spec: &toml::Value, file: &str) -> Result<Vec<Overrides>, ParamsError> {
+    let mut ret = match spec.get("grid") {
+        Some(grid) => grid_combinations(grid.as_table().ok_or_else(|| wrong_type(file, "grid", "a table", grid))?, file)?,
+        None => Vec::new(),
+    };
+    if let Some(runs) = spec.get("runs") {
+        let not_tables = || wrong_type(file, "runs", "a list of tables", runs);
+        for r in runs.as_array().ok_or_else(not_tables)? {
+            ret.push(r.as_table().ok_or_else(not_tables)?.iter().map(|(k, v)| (k.clone(), v.clone())).collect());
+        }
+    }
+    if ret.is_empty() {
+        ret.push(Vec::new()); // <- nothing to change, so just run the base file
+    }
+    Ok(ret)
+}
+
+pub fn run_file_path(output_directory: &str, run_id: usize) -> String {
+    format!("{}/run_{}.csv", output_directory, run_id)
+}
+
+impl Sweep {
+    // `spec_file_path` and `base_file_path` are only used to say where problems are
+    pub fn from_toml(spec: &toml::Value, spec_file_path: &str, base: &toml::value::Table, base_file_path: &str) -> Result<Sweep, ParamsError> {
+        let output_directory = match spec.get("output_directory") {
+            Some(d) => String::from(d.as_str().ok_or_else(|| wrong_type(spec_file_path, "output_directory", "a string", d))?),
+            None => String::from("sweep_output"),
+        };
+        let threads = match spec.get("threads") {
+            Some(t) => match t.as_integer() {
+                Some(i) if i >= 1 => i as usize,
+                Some(i) => {
+                    return Err(ParamsError::Inconsistent {
+                        file: String::from(spec_file_path),
+                        reason: format!("threads ({}) should be at least 1", i),
+                    })
+                }
+                None => return Err(wrong_type(spec_file_path, "threads", "a positive integer", t)),
+            },
+            None => available_threads(),
+        };
+
+        let mut override_keys: Vec<String> = Vec::new();
+        let mut runs = Vec::new();
+        for (run_id, overrides) in run_overrides(spec, spec_file_path)?.into_iter().enumerate() {
+            let mut table = base.clone();
+            for (k, v) in &overrides {
+                if !override_keys.contains(k) {
+                    override_keys.push(k.clone());
+                }
+                table.insert(k.clone(), v.clone());
+            }
+            table.insert(
+                String::from("output_file_path"),
+                toml::Value::String(run_file_path(&output_directory, run_id)),
+            );
+            // Parsing everything up front means a typo in the last combination doesn't show up hours into the sweep
+            let params = params_from_toml(toml::Value::Table(table), &format!("{} with the overrides of run {}", base_file_path, run_id))?;
+            runs.push((overrides, params));
+        }
+        Ok(Sweep {
+            output_directory,
+            threads,
+            override_keys,
+            runs,
+        })
+    }
+
+    pub fn write_manifest(&self) -> std::io::Result<()> {
+        let mut f = File::create(format!("{}/manifest.csv", self.output_directory))?;
+        let mut header = vec![String::from("run_id"), String::from("output_file_path"), String::from("seed")];
+        header.extend(self.override_keys.iter().cloned());
+        writeln!(f, "{}", header.iter().map(|h| csv_field(h)).collect::<Vec<String>>().join(","))?;
+
+        for (run_id, (overrides, params)) in self.runs.iter().enumerate() {
+            let mut row = vec![run_id.to_string(), params.output_file_path.clone(), params.seed.to_string()];
+            for k in &self.override_keys {
+                row.push(match overrides.iter().find(|(ok, _)| ok == k) {
+                    Some((_, toml::Value::String(s))) => s.clone(),
+                    Some((_, v)) => v.to_string(),
+                    None => String::new(), // <- this run kept the base value
+                });
+            }
+            writeln!(f, "{}", row.iter().map(|v| csv_field(v)).collect::<Vec<String>>().join(","))?;
+        }
+        Ok(())
+    }
+
+    pub fn run(self, how_many_reps: u64) {
+        run_in_parallel(self.runs.into_iter().map(|(_, p)| p).collect(), self.threads, how_many_reps)
+    }
+}
+
+// Runs are handed out in order to `threads` workers; a run that panics is reported and the rest carry on
+pub fn run_in_parallel(runs: Vec<Params>, threads: usize, how_many_reps: u64) {
+    let mut queue: Vec<(usize, Params)> = runs.into_iter().enumerate().collect();
+    queue.reverse();
+    let queue = Arc::new(Mutex::new(queue));
+    let workers: Vec<thread::JoinHandle<()>> = (0..threads.max(1))
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().pop();
+                match next {
+                    Some((run_id, params)) => match panic::catch_unwind(panic::AssertUnwindSafe(|| single_run(&params, how_many_reps))) {
+                        Ok(_) => println!("Run {} done ({})", run_id, params.output_file_path),
+                        Err(_) => println!("Run {} failed ({})", run_id, params.output_file_path),
+                    },
+                    None => break,
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+}
+
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+fn single_run(params: &Params, how_many_reps: u64) {
+    if let Err(e) = file_io::write_effective_params(params) {
+        println!("Couldn't write the parameters used next to {}: {}", params.output_file_path, e);
+    }
+    let mut sim_state = SimState::initial_state(params);
+    let mut recording_state = recorders::RecordingState::initial_state(params).unwrap_or_else(|| panic!("Couldn't create recording state"));
+    headless::run_headless(&mut sim_state, params, &mut recording_state, how_many_reps, false)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        String::from(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_io::defaults_table;
+
+    #[test]
+    fn grid_and_runs_are_both_swept() {
+        let spec = r#"
+            output_directory = "somewhere"
+            [grid]
+            how_smooth = [4, 8, 12]
+            compression_factor = [1.05, 1.1]
+            [[runs]]
+            how_smooth = 20
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+        let sweep = Sweep::from_toml(&spec, "sweep.toml", &defaults_table(), "parameters.toml").unwrap();
+
+        assert_eq!(sweep.runs.len(), 3 * 2 + 1);
+        let mut smoothnesses: Vec<usize> = sweep.runs.iter().map(|(_, p)| p.how_smooth).collect();
+        smoothnesses.sort();
+        assert_eq!(smoothnesses, vec![4, 4, 8, 8, 12, 12, 20]);
+        assert_eq!(sweep.runs[6].1.compression_factor, defaults_table().get("compression_factor").unwrap().as_float().unwrap());
+        assert_eq!(sweep.runs[3].1.output_file_path, "somewhere/run_3.csv");
+    }
+
+    #[test]
+    fn no_overrides_means_one_run_of_the_base_file() {
+        let sweep = Sweep::from_toml(&"threads = 2".parse::<toml::Value>().unwrap(), "sweep.toml", &defaults_table(), "parameters.toml").unwrap();
+        assert_eq!(sweep.runs.len(), 1);
+        assert_eq!(sweep.threads, 2);
+    }
+
+    #[test]
+    fn bad_sweep_files_are_errors_naming_the_key() {
+        for (spec, key) in &[
+            ("grid = 3", "grid"),
+            ("grid = {how_smooth = 4}", "grid.how_smooth"),
+            ("runs = \"x\"", "runs"),
+            ("runs = [1]", "runs"),
+            ("output_directory = 1", "output_directory"),
+            ("threads = \"4\"", "threads"),
+        ] {
+            match Sweep::from_toml(&spec.parse::<toml::Value>().unwrap(), "sweep.toml", &defaults_table(), "parameters.toml") {
+                Err(e @ ParamsError::WrongType { .. }) => assert!(e.to_string().starts_with(&format!("sweep.toml: \"{}\"", key)), "{}", e),
+                _ => panic!("{} should have been rejected", spec),
+            }
+        }
+        for spec in &["threads = 0", "threads = -1"] {
+            match Sweep::from_toml(&spec.parse::<toml::Value>().unwrap(), "sweep.toml", &defaults_table(), "parameters.toml") {
+                Err(ParamsError::Inconsistent { reason, .. }) => assert!(reason.contains("threads"), "{}", reason),
+                _ => panic!("{} should have been rejected", spec),
+            }
+        }
+    }
+}
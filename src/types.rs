@@ -1,10 +1,32 @@
-#[derive(Clone)]
+use cooling::CoolingSchedule;
+use initial_shape::InitialShape;
+use optimizer::Engine;
+use parallel_tempering::TemperingParams;
+use renderer::raster::FrameParams;
+use renderer::svg::SvgParams;
+
+// Every source of randomness in a simulation comes from one of these, seeded from Params::seed
+pub type SimRng = rand_pcg::Pcg64;
+
+// The seed `i` after `seed`, for runs that need one each. Wraps around at i64::MAX rather than u64::MAX, so it can still
+// be written out as a TOML integer
+pub fn nth_seed(seed: u64, i: u64) -> u64 {
+    seed.wrapping_add(i) & i64::MAX as u64
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Params {
     pub initial_thickness: f64,
     pub initial_radius: f64,
+    pub initial_shape: InitialShape,
     pub initial_num_points: usize,
     pub initial_temperature: f64,
-    pub initial_gray_matter_area: f64,
+    pub initial_gray_matter_area: f64, // <- of initial_shape, what gray_matter_stretch compares against
+    pub am: f64, // <- energy = am * white_matter^ap + dm * (1 + gray_matter_stretch)^dp
+    pub ap: f64,
+    pub dm: f64,
+    pub dp: f64,
+    pub energy_terms: Vec<(String, f64)>, // <- (term name, weight); energy is the weighted sum of these terms
     pub compression_factor: f64,
     pub softness_factor: f64, // <- how much should closeness of nodes in different surfaces impact pushes?
     pub how_smooth: usize,
@@ -13,6 +35,14 @@ pub struct Params {
     pub node_deletion_threshold: f64,
     pub low_high: (f64, f64),
     pub recorders: Vec<String>,
-    pub temperature_param: f64,
+    pub cooling_schedule: CoolingSchedule,
     pub output_file_path: String,
+    pub seed: u64,
+    pub checkpoint_interval: Option<u64>, // <- write a checkpoint every this many steps, if set
+    pub snapshot_interval: Option<u64>,   // <- write both layers' coordinates every this many steps, if set
+    pub thickness_interval: Option<u64>,  // <- write the thickness at every outer node every this many steps, if set
+    pub svg: Option<SvgParams>,
+    pub frames: Option<FrameParams>, // <- PNG frames of the surface every so many steps, and maybe a GIF of them
+    pub parallel_tempering: Option<TemperingParams>,
+    pub engine: Engine,
 }